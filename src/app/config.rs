@@ -1,6 +1,9 @@
 use anyhow::anyhow;
 use ini::{Ini, Properties};
-use std::{fmt::Display, net::IpAddr};
+use std::{collections::HashMap, fmt::Display, net::IpAddr, path::PathBuf, str::FromStr};
+use tracing_subscriber::EnvFilter;
+
+use crate::utils::{DB_URL, UPDATE_PERIOD};
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct IgdbConfig {
@@ -15,7 +18,9 @@ impl IgdbConfig {
             client_secret: client_secret.into(),
         }
     }
-    pub fn from_init_section(igdb_section: Option<&Properties>) -> anyhow::Result<Self> {
+    // The section is optional, but both credentials
+    // are required when it is present
+    pub fn from_init_section(igdb_section: Option<&Properties>) -> anyhow::Result<Option<Self>> {
         match igdb_section {
             Some(section) => {
                 let client_id: String = section
@@ -26,12 +31,97 @@ impl IgdbConfig {
                     .get("client_secret")
                     .ok_or_else(|| anyhow!("client_secret is required"))?
                     .into();
-                Ok(IgdbConfig {
+                Ok(Some(IgdbConfig {
                     client_id,
                     client_secret,
-                })
+                }))
             }
-            None => Err(anyhow!("igdb settings are required")),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProviderKind {
+    Igdb,
+    Steam,
+    Metacritic,
+}
+
+impl FromStr for ProviderKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "igdb" => Ok(ProviderKind::Igdb),
+            "steam" => Ok(ProviderKind::Steam),
+            "metacritic" => Ok(ProviderKind::Metacritic),
+            other => Err(anyhow!("unknown metadata provider {}", other)),
+        }
+    }
+}
+
+impl Display for ProviderKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProviderKind::Igdb => write!(f, "IGDB"),
+            ProviderKind::Steam => write!(f, "Steam"),
+            ProviderKind::Metacritic => write!(f, "Metacritic"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataConfig {
+    pub providers: Vec<ProviderKind>,
+    // in seconds, providers without ttl use the cache max_age
+    pub ttls: HashMap<ProviderKind, u64>,
+}
+
+impl MetadataConfig {
+    pub fn from_init_section(metadata_section: Option<&Properties>) -> anyhow::Result<Self> {
+        let mut metadata_config = MetadataConfig::default();
+        if let Some(providers) = metadata_section.and_then(|section| section.get("providers")) {
+            metadata_config.providers = vec![];
+            for provider in providers.split(',').filter(|x| !x.trim().is_empty()) {
+                let provider: ProviderKind = provider.parse()?;
+                if metadata_config.providers.contains(&provider) {
+                    return Err(anyhow!("{} is listed more than once", provider));
+                }
+                metadata_config.providers.push(provider);
+            }
+        }
+        if let Some(section) = metadata_section {
+            for (provider, key) in [
+                (ProviderKind::Igdb, "igdb_ttl"),
+                (ProviderKind::Steam, "steam_ttl"),
+                (ProviderKind::Metacritic, "metacritic_ttl"),
+            ] {
+                if let Some(ttl) = section.get(key) {
+                    metadata_config.ttls.insert(provider, ttl.parse::<u64>()?);
+                }
+            }
+        }
+        Ok(metadata_config)
+    }
+    pub fn is_enabled(&self, provider: ProviderKind) -> bool {
+        self.providers.contains(&provider)
+    }
+    // provider is the name of the provider as recorded in the provenance
+    pub fn ttl(&self, provider: &str) -> Option<u64> {
+        let provider: ProviderKind = provider.parse().ok()?;
+        self.ttls.get(&provider).copied()
+    }
+}
+
+impl Default for MetadataConfig {
+    fn default() -> Self {
+        Self {
+            providers: vec![
+                ProviderKind::Igdb,
+                ProviderKind::Metacritic,
+                ProviderKind::Steam,
+            ],
+            ttls: HashMap::new(),
         }
     }
 }
@@ -106,20 +196,227 @@ impl Default for ServerConfig {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheConfig {
+    // no persistence when the path is not set
+    pub path: Option<PathBuf>,
+    // in seconds
+    pub flush_period: u64,
+    // in seconds
+    pub max_age: u64,
+}
+
+impl CacheConfig {
+    pub fn from_init_section(cache_section: Option<&Properties>) -> anyhow::Result<Self> {
+        let mut cache_config = CacheConfig::default();
+        if let Some(section) = cache_section {
+            if let Some(path) = section.get("path") {
+                cache_config.path = Some(PathBuf::from(path));
+            }
+            if let Some(flush_period) = section.get("flush_period") {
+                cache_config.flush_period = flush_period.parse::<u64>()?;
+            }
+            if let Some(max_age) = section.get("max_age") {
+                cache_config.max_age = max_age.parse::<u64>()?;
+            }
+        }
+        if cache_config.flush_period == 0 {
+            return Err(anyhow!("flush_period must be greater than 0"));
+        }
+        Ok(cache_config)
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            flush_period: 300,
+            // one week
+            max_age: 604800,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseSource {
+    // remote database fetched over http(s)
+    Url(String),
+    // local database reloaded when modified
+    File(PathBuf),
+    // local clone of the database repository, pulled before each update
+    Git { repository: PathBuf, file: String },
+}
+
+impl Default for DatabaseSource {
+    fn default() -> Self {
+        DatabaseSource::Url(DB_URL.into())
+    }
+}
+
+impl Display for DatabaseSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DatabaseSource::Url(url) => write!(f, "{}", url),
+            DatabaseSource::File(path) => write!(f, "{}", path.display()),
+            DatabaseSource::Git { repository, file } => {
+                write!(f, "{} (git)", repository.join(file).display())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseConfig {
+    pub source: DatabaseSource,
+    // in seconds
+    pub update_period: u64,
+}
+
+impl DatabaseConfig {
+    pub fn from_init_section(database_section: Option<&Properties>) -> anyhow::Result<Self> {
+        let mut database_config = DatabaseConfig::default();
+        if let Some(section) = database_section {
+            let url = section.get("url");
+            let file = section.get("file");
+            let git = section.get("git");
+            database_config.source = match (url, file, git) {
+                (None, None, None) => DatabaseSource::default(),
+                (Some(url), None, None) => {
+                    if !(url.starts_with("http://") || url.starts_with("https://")) {
+                        return Err(anyhow!("url must start with http:// or https://"));
+                    }
+                    DatabaseSource::Url(url.into())
+                }
+                (None, Some(file), None) => DatabaseSource::File(PathBuf::from(file)),
+                (None, None, Some(git)) => DatabaseSource::Git {
+                    repository: PathBuf::from(git),
+                    file: section.get("git_file").unwrap_or("openbsd-games.db").into(),
+                },
+                _ => return Err(anyhow!("only one of url, file and git can be set")),
+            };
+            if let Some(update_period) = section.get("update_period") {
+                database_config.update_period = update_period.parse::<u64>()?;
+            }
+        }
+        if database_config.update_period == 0 {
+            return Err(anyhow!("update_period must be greater than 0"));
+        }
+        Ok(database_config)
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            source: DatabaseSource::default(),
+            update_period: UPDATE_PERIOD,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LogConfig {
+    // same syntax as POBSD_SERVER_LOG, which is used when not set
+    pub filter: Option<String>,
+}
+
+impl LogConfig {
+    pub fn from_init_section(log_section: Option<&Properties>) -> anyhow::Result<Self> {
+        let filter = log_section
+            .and_then(|section| section.get("filter"))
+            .map(String::from);
+        if let Some(ref filter) = filter {
+            EnvFilter::builder()
+                .parse(filter)
+                .map_err(|e| anyhow!("invalid log filter {}: {}", filter, e))?;
+        }
+        Ok(Self { filter })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaginationConfig {
+    // number of games on a page when the client does not choose
+    pub per_page: usize,
+    // the most a client can ask for
+    pub max_per_page: usize,
+}
+
+impl PaginationConfig {
+    pub fn from_init_section(pagination_section: Option<&Properties>) -> anyhow::Result<Self> {
+        let mut pagination_config = PaginationConfig::default();
+        if let Some(section) = pagination_section {
+            if let Some(per_page) = section.get("per_page") {
+                pagination_config.per_page = per_page.parse::<usize>()?;
+            }
+            if let Some(max_per_page) = section.get("max_per_page") {
+                pagination_config.max_per_page = max_per_page.parse::<usize>()?;
+            }
+        }
+        if pagination_config.per_page == 0 {
+            return Err(anyhow!("per_page must be greater than 0"));
+        }
+        if pagination_config.max_per_page < pagination_config.per_page {
+            return Err(anyhow!("max_per_page must be at least per_page"));
+        }
+        Ok(pagination_config)
+    }
+    // The size asked for by the client, within the bounds
+    pub fn per_page(&self, per_page: Option<usize>) -> usize {
+        per_page
+            .unwrap_or(self.per_page)
+            .clamp(1, self.max_per_page)
+    }
+}
+
+impl Default for PaginationConfig {
+    fn default() -> Self {
+        Self {
+            per_page: 15,
+            max_per_page: 100,
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct AppConfig {
-    pub igdb_config: IgdbConfig,
+    pub igdb_config: Option<IgdbConfig>,
+    pub metadata_config: MetadataConfig,
     pub server_config: ServerConfig,
+    pub cache_config: CacheConfig,
+    pub database_config: DatabaseConfig,
+    pub log_config: LogConfig,
+    pub pagination_config: PaginationConfig,
 }
 
 impl AppConfig {
     pub fn from_init_file(init_file_path: &str) -> anyhow::Result<Self> {
         let config_file = Ini::load_from_file(init_file_path)?;
         let igdb_config = IgdbConfig::from_init_section(config_file.section(Some("Igdb")))?;
+        let mut metadata_config =
+            MetadataConfig::from_init_section(config_file.section(Some("Metadata")))?;
+        if igdb_config.is_none() && metadata_config.is_enabled(ProviderKind::Igdb) {
+            tracing::warn!("No Igdb section in the configuration, IGDB is disabled");
+            metadata_config
+                .providers
+                .retain(|provider| provider.ne(&ProviderKind::Igdb));
+        }
         let server_config = ServerConfig::from_init_section(config_file.section(Some("Server")))?;
+        let cache_config = CacheConfig::from_init_section(config_file.section(Some("Cache")))?;
+        let database_config =
+            DatabaseConfig::from_init_section(config_file.section(Some("Database")))?;
+        let log_config = LogConfig::from_init_section(config_file.section(Some("Log")))?;
+        let pagination_config =
+            PaginationConfig::from_init_section(config_file.section(Some("Pagination")))?;
         Ok(Self {
             igdb_config,
+            metadata_config,
             server_config,
+            cache_config,
+            database_config,
+            log_config,
+            pagination_config,
         })
     }
 }
@@ -129,6 +426,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::manual_unwrap_or_default)]
     fn load_well_formed_config_file() {
         let app_config = match AppConfig::from_init_file("tests/config_file/pobsd-server.conf") {
             Ok(app_config) => app_config,
@@ -146,7 +444,111 @@ mod tests {
             client_id: String::from("super_client_id_123456789"),
             client_secret: String::from("super_client_secret_987654321"),
         };
-        assert_eq!(igdb_config, igdb_config_test);
+        assert_eq!(igdb_config, Some(igdb_config_test));
+        assert_eq!(app_config.metadata_config, MetadataConfig::default());
+        assert_eq!(app_config.cache_config, CacheConfig::default());
+        assert_eq!(app_config.database_config, DatabaseConfig::default());
+    }
+    #[test]
+    fn load_config_file_with_database_file() {
+        let app_config = AppConfig::from_init_file("tests/config_file/pobsd-server-db-file.conf")
+            .expect("Failed to load config file");
+        let database_config_test = DatabaseConfig {
+            source: DatabaseSource::File(PathBuf::from("tests/db/openbsd-games.db")),
+            update_period: 60,
+        };
+        assert_eq!(app_config.database_config, database_config_test);
+    }
+    #[test]
+    fn load_config_file_with_database_git() {
+        let app_config = AppConfig::from_init_file("tests/config_file/pobsd-server-db-git.conf")
+            .expect("Failed to load config file");
+        let database_config_test = DatabaseConfig {
+            source: DatabaseSource::Git {
+                repository: PathBuf::from("/var/pobsd-server/OpenBSD-Games-Database"),
+                file: "openbsd-games.db".into(),
+            },
+            update_period: UPDATE_PERIOD,
+        };
+        assert_eq!(app_config.database_config, database_config_test);
+    }
+    #[test]
+    fn load_config_file_with_several_database_sources() {
+        let app_config =
+            AppConfig::from_init_file("tests/config_file/pobsd-server-db-several-sources.conf");
+        assert!(app_config.is_err());
+    }
+    #[test]
+    fn load_config_file_without_igdb_section() {
+        let app_config = AppConfig::from_init_file("tests/config_file/pobsd-server-no-igdb.conf")
+            .expect("Failed to load config file");
+        assert_eq!(app_config.igdb_config, None);
+        let metadata_config_test = MetadataConfig {
+            providers: vec![ProviderKind::Metacritic, ProviderKind::Steam],
+            ttls: HashMap::new(),
+        };
+        assert_eq!(app_config.metadata_config, metadata_config_test);
+    }
+    #[test]
+    fn load_config_file_with_providers() {
+        let app_config = AppConfig::from_init_file("tests/config_file/pobsd-server-providers.conf")
+            .expect("Failed to load config file");
+        let metadata_config_test = MetadataConfig {
+            providers: vec![ProviderKind::Steam, ProviderKind::Igdb],
+            ttls: HashMap::from([(ProviderKind::Metacritic, 86400)]),
+        };
+        assert_eq!(app_config.metadata_config, metadata_config_test);
+    }
+    #[test]
+    fn load_config_file_with_unknown_provider() {
+        let app_config =
+            AppConfig::from_init_file("tests/config_file/pobsd-server-unknown-provider.conf");
+        assert!(app_config.is_err());
+    }
+    #[test]
+    fn load_config_file_with_cache_section() {
+        let app_config = AppConfig::from_init_file("tests/config_file/pobsd-server-cache.conf")
+            .expect("Failed to load config file");
+        let cache_config_test = CacheConfig {
+            path: Some(PathBuf::from("/var/pobsd-server/metadata.json")),
+            flush_period: 60,
+            max_age: 3600,
+        };
+        assert_eq!(app_config.cache_config, cache_config_test);
+    }
+    #[test]
+    fn load_config_file_with_log_section() {
+        let app_config = AppConfig::from_init_file("tests/config_file/pobsd-server-log.conf")
+            .expect("Failed to load config file");
+        let log_config_test = LogConfig {
+            filter: Some("pobsd_server=debug,tower_http=info".into()),
+        };
+        assert_eq!(app_config.log_config, log_config_test);
+        let app_config =
+            AppConfig::from_init_file("tests/config_file/pobsd-server-log-invalid-filter.conf");
+        assert!(app_config.is_err());
+    }
+    #[test]
+    fn load_config_file_with_pagination_section() {
+        let app_config =
+            AppConfig::from_init_file("tests/config_file/pobsd-server-pagination.conf")
+                .expect("Failed to load config file");
+        let pagination_config = app_config.pagination_config;
+        assert_eq!(pagination_config.per_page, 20);
+        assert_eq!(pagination_config.max_per_page, 200);
+        assert_eq!(pagination_config.per_page(None), 20);
+        assert_eq!(pagination_config.per_page(Some(0)), 1);
+        assert_eq!(pagination_config.per_page(Some(500)), 200);
+        let app_config =
+            AppConfig::from_init_file("tests/config_file/pobsd-server-pagination-invalid-max.conf");
+        assert!(app_config.is_err());
+    }
+    #[test]
+    fn load_config_file_with_invalid_flush_period() {
+        let app_config = AppConfig::from_init_file(
+            "tests/config_file/pobsd-server-cache-invalid-flush-period.conf",
+        );
+        assert!(app_config.is_err());
     }
     #[test]
     fn load_not_well_formed_config_file_with_invalid_port() {
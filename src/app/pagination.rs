@@ -1,6 +1,7 @@
+use serde::Serialize;
 use std::cmp::Ordering;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Serialize)]
 pub struct Page {
     pub first_element: usize,
     pub last_element: usize,
@@ -21,9 +22,11 @@ impl Paginator {
             item_per_page,
         }
     }
+    // is_multiple_of needs a more recent toolchain
+    #[allow(clippy::manual_is_multiple_of)]
     pub fn page(&self, current_page: usize) -> Option<Page> {
-        // no meaningful result in that case
-        if self.item_per_page.eq(&0) || self.item_number.eq(&0) {
+        // no meaningful result in that case, pages start at 1
+        if self.item_per_page.eq(&0) || self.item_number.eq(&0) || current_page.eq(&0) {
             return None;
         }
         let last_page = if self.item_number % self.item_per_page == 0 {
@@ -31,7 +34,8 @@ impl Paginator {
         } else {
             self.item_number / self.item_per_page + 1
         };
-        let first_element = self.item_per_page * (current_page - 1);
+        // a page far beyond the last one would overflow
+        let first_element = self.item_per_page.checked_mul(current_page - 1)?;
         match current_page.cmp(&last_page) {
             Ordering::Greater => None,
             Ordering::Equal => {
@@ -82,6 +86,24 @@ mod tests {
         assert_eq!(page, None);
     }
     #[test]
+    fn test_paginator_page_zero() {
+        let paginator = Paginator::new(100, 10);
+        let page = paginator.page(0);
+        assert_eq!(page, None);
+    }
+    #[test]
+    fn test_paginator_one_item_per_page() {
+        let paginator = Paginator::new(3, 1);
+        let page = paginator.page(2);
+        let expected_page = Page {
+            first_element: 1,
+            last_element: 1,
+            current_page: 2,
+            last_page: 3,
+        };
+        assert_eq!(page, Some(expected_page));
+    }
+    #[test]
     fn test_paginator_after_last_page() {
         let paginator = Paginator::new(100, 30);
         let page = paginator.page(10);
@@ -195,4 +217,9 @@ mod tests {
         assert_eq!(page2, Some(expected_page2));
         assert_eq!(page3, Some(expected_page3));
     }
+    #[test]
+    fn test_paginator_page_far_beyond_the_last() {
+        let paginator = Paginator::new(140, 50);
+        assert_eq!(paginator.page(usize::MAX), None);
+    }
 }
@@ -1,15 +1,22 @@
 use libpobsd::Game;
+use serde::Serialize;
 
 use crate::db::data::GameMetaData;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct GameRepresentation {
     pub game: Game,
     pub metadata: Option<GameMetaData>,
+    // the metadata are being fetched
+    pub metadata_pending: bool,
 }
 
 impl GameRepresentation {
     pub fn new(game: Game, metadata: Option<GameMetaData>) -> Self {
-        Self { game, metadata }
+        Self {
+            game,
+            metadata,
+            metadata_pending: false,
+        }
     }
 }
@@ -0,0 +1,371 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{sync::watch::Receiver as WatchReceiver, task::JoinHandle};
+
+use crate::{
+    app::config::MetadataConfig,
+    db::{
+        connector::DbCon,
+        data::{GameMetaData, Source},
+        responses::AppDbResponse,
+    },
+    provider::{FetchErrorKind, ProviderFailure},
+    utils::sleep_or_shutdown,
+};
+
+// Bump when the layout of the cache file changes,
+// older files are then ignored instead of misread.
+pub const CACHE_VERSION: u32 = 1;
+// longest delay between two lookups of a game
+// for which every provider failed, in seconds
+pub const MAX_RETRY_DELAY: u64 = 604800;
+
+// Persistent maps, a copy shares its entries with the
+// original so a write does not copy the whole map
+pub type MetaDataEntries = im::HashMap<u32, Arc<CachedMetaData>>;
+pub type FailedLookups = im::HashMap<u32, FailedLookup>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedMetaData {
+    pub metadata: GameMetaData,
+    pub fetched_at: DateTime<Utc>,
+    // providers that failed the lookup while others answered
+    #[serde(default)]
+    pub failures: Vec<ProviderFailure>,
+}
+
+impl CachedMetaData {
+    pub fn new(metadata: GameMetaData) -> Self {
+        Self {
+            metadata,
+            fetched_at: Utc::now(),
+            failures: vec![],
+        }
+    }
+    pub fn with_failures(mut self, failures: Vec<ProviderFailure>) -> Self {
+        self.failures = failures;
+        self
+    }
+    // Each field is checked against the ttl of the provider it comes from,
+    // max_age is used for providers without ttl and entries without provenance.
+    // A provider that could not be reached is asked again after its retry delay.
+    pub fn is_fresh(&self, metadata_config: &MetadataConfig, max_age: u64) -> bool {
+        let now = Utc::now();
+        let is_young = |fetched_at: DateTime<Utc>, ttl: u64| -> bool {
+            (now - fetched_at).num_seconds() < ttl as i64
+        };
+        if self.failures.iter().any(|failure| {
+            failure.kind.is_transient() && !is_young(self.fetched_at, failure.kind.retry_delay())
+        }) {
+            return false;
+        }
+        let provenance = &self.metadata.provenance;
+        let sources: Vec<&Source> = [
+            &provenance.summary,
+            &provenance.story_line,
+            &provenance.screenshots,
+            &provenance.cover,
+            &provenance.metacritic,
+        ]
+        .into_iter()
+        .flatten()
+        // the fields kept from a provider that failed
+        // are fetched again with it
+        .filter(|source| {
+            !self
+                .failures
+                .iter()
+                .any(|failure| failure.provider == source.provider)
+        })
+        .collect();
+        if sources.is_empty() {
+            return is_young(self.fetched_at, max_age);
+        }
+        sources.into_iter().all(|source| {
+            let ttl = metadata_config.ttl(&source.provider).unwrap_or(max_age);
+            is_young(source.fetched_at, ttl)
+        })
+    }
+}
+
+// A lookup for which every provider failed, the game
+// is not looked up again before next_retry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedLookup {
+    pub kind: FetchErrorKind,
+    pub attempts: u32,
+    pub last_attempt: DateTime<Utc>,
+    pub next_retry: DateTime<Utc>,
+}
+
+impl FailedLookup {
+    pub fn new(kind: FetchErrorKind) -> Self {
+        let mut failed_lookup = Self {
+            kind,
+            attempts: 0,
+            last_attempt: Utc::now(),
+            next_retry: Utc::now(),
+        };
+        failed_lookup.record(kind);
+        failed_lookup
+    }
+    // The delay doubles with each failed attempt
+    pub fn record(&mut self, kind: FetchErrorKind) {
+        self.kind = kind;
+        self.attempts += 1;
+        self.last_attempt = Utc::now();
+        let delay = kind
+            .retry_delay()
+            .saturating_mul(2u64.saturating_pow(self.attempts - 1))
+            .min(MAX_RETRY_DELAY);
+        self.next_retry = self.last_attempt + chrono::Duration::seconds(delay as i64);
+    }
+    pub fn can_retry(&self) -> bool {
+        Utc::now() >= self.next_retry
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetaDataCache {
+    pub version: u32,
+    pub entries: HashMap<u32, CachedMetaData>,
+    #[serde(default)]
+    pub failures: HashMap<u32, FailedLookup>,
+}
+
+impl Default for MetaDataCache {
+    fn default() -> Self {
+        Self {
+            version: CACHE_VERSION,
+            entries: HashMap::default(),
+            failures: HashMap::default(),
+        }
+    }
+}
+
+impl MetaDataCache {
+    pub fn new(entries: HashMap<u32, CachedMetaData>) -> Self {
+        Self {
+            version: CACHE_VERSION,
+            entries,
+            failures: HashMap::default(),
+        }
+    }
+    // A missing file or a file written with another version
+    // of the format gives an empty cache.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            tracing::info!("No metadata cache found at {}", path.display());
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read metadata cache {}", path.display()))?;
+        let cache: MetaDataCache = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse metadata cache {}", path.display()))?;
+        if cache.version != CACHE_VERSION {
+            tracing::warn!(
+                "Ignoring metadata cache with version {} (expected {})",
+                cache.version,
+                CACHE_VERSION
+            );
+            return Ok(Self::default());
+        }
+        tracing::info!("Loaded {} cached metadata entries", cache.entries.len());
+        Ok(cache)
+    }
+    // Write to a temporary file first and then rename it so a crash
+    // while writing never leaves a truncated cache behind.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string(self).context("Failed to serialize metadata cache")?;
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, content)
+            .with_context(|| format!("Failed to write metadata cache {}", path.display()))?;
+        std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to replace metadata cache {}", path.display()))?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct CacheFlusher {
+    db_con: Arc<DbCon>,
+    path: PathBuf,
+    flush_period: u64,
+}
+
+impl CacheFlusher {
+    pub fn init(db_con: Arc<DbCon>, path: PathBuf, flush_period: u64) -> Self {
+        Self {
+            db_con,
+            path,
+            flush_period,
+        }
+    }
+    pub async fn flush(&self) -> anyhow::Result<()> {
+        match self.db_con.get_metadata_cache().await {
+            AppDbResponse::MetaDataCache(cache) => {
+                let path = self.path.clone();
+                tokio::task::spawn_blocking(move || cache.save(&path))
+                    .await
+                    .context("Failed to join cache writer")??;
+                tracing::debug!("Metadata cache flushed to {}", self.path.display());
+                Ok(())
+            }
+            _ => Err(anyhow::anyhow!("Failed to retrieve the metadata cache")),
+        }
+    }
+    // The last flush is left to the caller once
+    // the other tasks have stopped
+    pub fn launch(&self, mut shutdown: WatchReceiver<bool>) -> JoinHandle<()> {
+        let cache_flusher = self.clone();
+        tokio::spawn(async move {
+            while sleep_or_shutdown(cache_flusher.flush_period, &mut shutdown).await {
+                if let Err(e) = cache_flusher.flush().await {
+                    tracing::warn!("Failed to flush metadata cache: {e:#}");
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::config::ProviderKind;
+
+    #[test]
+    fn test_cache_round_trip() {
+        let path = std::env::temp_dir().join("pobsd-server-test-cache-round-trip.json");
+        let metadata = GameMetaData {
+            summary: Some("summary".into()),
+            ..Default::default()
+        };
+        let mut entries = HashMap::new();
+        entries.insert(42, CachedMetaData::new(metadata));
+        MetaDataCache::new(entries).save(&path).unwrap();
+        let cache = MetaDataCache::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(cache.version, CACHE_VERSION);
+        assert_eq!(
+            cache.entries.get(&42).unwrap().metadata.summary,
+            Some("summary".into())
+        );
+    }
+    #[test]
+    fn test_cache_with_other_version_is_ignored() {
+        let path = std::env::temp_dir().join("pobsd-server-test-cache-other-version.json");
+        let mut entries = HashMap::new();
+        entries.insert(42, CachedMetaData::new(GameMetaData::default()));
+        let mut cache = MetaDataCache::new(entries);
+        cache.version = CACHE_VERSION + 1;
+        cache.save(&path).unwrap();
+        let cache = MetaDataCache::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(cache.entries.is_empty());
+    }
+    #[test]
+    fn test_missing_cache_is_empty() {
+        let path = std::env::temp_dir().join("pobsd-server-test-cache-does-not-exist.json");
+        let cache = MetaDataCache::load(&path).unwrap();
+        assert!(cache.entries.is_empty());
+    }
+    #[test]
+    fn test_failed_lookup_backoff() {
+        let mut failed_lookup = FailedLookup::new(FetchErrorKind::Network);
+        assert!(!failed_lookup.can_retry());
+        let first_delay = failed_lookup.next_retry - failed_lookup.last_attempt;
+        assert_eq!(first_delay.num_seconds(), 300);
+        failed_lookup.record(FetchErrorKind::Network);
+        let second_delay = failed_lookup.next_retry - failed_lookup.last_attempt;
+        assert_eq!(second_delay.num_seconds(), 600);
+        for _ in 0..20 {
+            failed_lookup.record(FetchErrorKind::NotFound);
+        }
+        let last_delay = failed_lookup.next_retry - failed_lookup.last_attempt;
+        assert_eq!(last_delay.num_seconds(), MAX_RETRY_DELAY as i64);
+        assert_eq!(failed_lookup.attempts, 22);
+    }
+    #[test]
+    fn test_cache_freshness() {
+        let metadata_config = MetadataConfig::default();
+        let mut entry = CachedMetaData::new(GameMetaData::default());
+        assert!(entry.is_fresh(&metadata_config, 60));
+        entry.fetched_at = Utc::now() - chrono::Duration::try_seconds(120).unwrap();
+        assert!(!entry.is_fresh(&metadata_config, 60));
+    }
+    #[test]
+    fn test_cache_freshness_with_provider_ttl() {
+        let mut metadata_config = MetadataConfig::default();
+        metadata_config.ttls.insert(ProviderKind::Metacritic, 60);
+        let metadata = GameMetaData {
+            summary: Some("summary".into()),
+            ..Default::default()
+        }
+        .with_source("IGDB");
+        let mut entry = CachedMetaData::new(metadata);
+        let two_minutes_ago = Utc::now() - chrono::Duration::try_seconds(120).unwrap();
+        entry
+            .metadata
+            .provenance
+            .summary
+            .as_mut()
+            .unwrap()
+            .fetched_at = two_minutes_ago;
+        // IGDB falls back to max_age
+        assert!(entry.is_fresh(&metadata_config, 3600));
+        entry.metadata.metacritic = Some(Default::default());
+        entry.metadata.provenance.metacritic = Some(Source {
+            provider: "Metacritic".into(),
+            fetched_at: two_minutes_ago,
+        });
+        assert!(!entry.is_fresh(&metadata_config, 3600));
+    }
+    #[test]
+    fn test_cache_freshness_with_failed_provider() {
+        let metadata_config = MetadataConfig::default();
+        let failure = |kind| ProviderFailure {
+            provider: "IGDB".into(),
+            kind,
+        };
+        let mut entry = CachedMetaData::new(GameMetaData::default())
+            .with_failures(vec![failure(FetchErrorKind::Unavailable)]);
+        assert!(entry.is_fresh(&metadata_config, 86400));
+        // the provider is asked again after its retry delay
+        entry.fetched_at = Utc::now() - chrono::Duration::try_seconds(1000).unwrap();
+        assert!(!entry.is_fresh(&metadata_config, 86400));
+        // a provider that does not know the game is not
+        entry.failures = vec![failure(FetchErrorKind::NotFound)];
+        assert!(entry.is_fresh(&metadata_config, 86400));
+    }
+    #[test]
+    fn test_cache_freshness_of_fields_kept_from_failed_provider() {
+        let metadata_config = MetadataConfig::default();
+        let mut entry = CachedMetaData::new(
+            GameMetaData {
+                summary: Some("summary".into()),
+                ..Default::default()
+            }
+            .with_source("IGDB"),
+        )
+        .with_failures(vec![ProviderFailure {
+            provider: "IGDB".into(),
+            kind: FetchErrorKind::Network,
+        }]);
+        // the summary was fetched by an older lookup
+        entry
+            .metadata
+            .provenance
+            .summary
+            .as_mut()
+            .unwrap()
+            .fetched_at = Utc::now() - chrono::Duration::try_seconds(7200).unwrap();
+        assert!(entry.is_fresh(&metadata_config, 3600));
+    }
+}
@@ -0,0 +1,243 @@
+use chrono::{DateTime, Utc};
+use libpobsd::{Game, GameDataBase};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::provider::MetadataKeys;
+
+// number of updates kept in the history
+pub const CHANGELOG_SIZE: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GameRef {
+    pub uid: u32,
+    pub name: String,
+}
+
+impl From<&Game> for GameRef {
+    fn from(game: &Game) -> Self {
+        Self {
+            uid: game.uid,
+            name: game.name.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GameChange {
+    pub game: GameRef,
+    pub changes: Vec<FieldChange>,
+}
+
+impl GameChange {
+    pub fn has_field(&self, field: &str) -> bool {
+        self.changes.iter().any(|change| change.field.eq(field))
+    }
+}
+
+// What changed between two versions of the game database
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeLog {
+    pub date: DateTime<Utc>,
+    pub added: Vec<GameRef>,
+    pub removed: Vec<GameRef>,
+    pub changed: Vec<GameChange>,
+}
+
+fn join(items: &Option<Vec<String>>) -> Option<String> {
+    items.as_ref().map(|items| items.join(", "))
+}
+
+// The fields compared, as they would be displayed. The added
+// and updated dates are left out since they follow the changes.
+// So is the name: the uid is derived from it, a renamed game
+// is removed and added.
+fn get_fields(game: &Game) -> [(&'static str, Option<String>); 14] {
+    let stores = game.stores.as_ref().map(|stores| {
+        stores
+            .inner_ref()
+            .iter()
+            .map(|store| store.url.clone())
+            .collect::<Vec<String>>()
+            .join(" ")
+    });
+    let status = match game.status.message {
+        Some(ref message) => format!("{} ({})", game.status.status, message),
+        None => game.status.status.to_string(),
+    };
+    [
+        ("cover", game.cover.clone()),
+        ("engine", game.engine.clone()),
+        ("setup", game.setup.clone()),
+        ("runtime", game.runtime.clone()),
+        ("stores", stores),
+        ("hints", game.hints.clone()),
+        ("genres", join(&game.genres)),
+        ("tags", join(&game.tags)),
+        ("year", game.year.clone()),
+        ("devs", join(&game.devs)),
+        ("publishers", join(&game.publis)),
+        ("version", game.version.clone()),
+        ("status", Some(status)),
+        ("igdb_id", game.igdb_id.map(|igdb_id| igdb_id.to_string())),
+    ]
+}
+
+fn diff_games(old: &Game, new: &Game) -> Vec<FieldChange> {
+    get_fields(old)
+        .into_iter()
+        .zip(get_fields(new))
+        .filter(|((_, old), (_, new))| old.ne(new))
+        .map(|((field, old), (_, new))| FieldChange { field, old, new })
+        .collect()
+}
+
+impl ChangeLog {
+    pub fn diff(old: &GameDataBase, new: &GameDataBase) -> Self {
+        let old_games: HashMap<u32, &Game> = old
+            .get_all_games()
+            .into_iter()
+            .map(|game| (game.uid, game))
+            .collect();
+        let new_games: HashMap<u32, &Game> = new
+            .get_all_games()
+            .into_iter()
+            .map(|game| (game.uid, game))
+            .collect();
+        let mut added: Vec<GameRef> = vec![];
+        let mut changed: Vec<GameChange> = vec![];
+        // the games of the new database are already sorted by name
+        for game in new.get_all_games() {
+            match old_games.get(&game.uid) {
+                Some(old_game) => {
+                    let changes = diff_games(old_game, game);
+                    if !changes.is_empty() {
+                        changed.push(GameChange {
+                            game: GameRef::from(game),
+                            changes,
+                        });
+                    }
+                }
+                None => added.push(GameRef::from(game)),
+            }
+        }
+        let removed: Vec<GameRef> = old
+            .get_all_games()
+            .into_iter()
+            .filter(|game| !new_games.contains_key(&game.uid))
+            .map(GameRef::from)
+            .collect();
+        Self {
+            date: Utc::now(),
+            added,
+            removed,
+            changed,
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+    // Changed games whose metadata would be looked up with other keys,
+    // old and new must be the databases the changelog was computed from
+    pub fn changed_keys(&self, old: &GameDataBase, new: &GameDataBase) -> HashSet<u32> {
+        self.changed
+            .iter()
+            .map(|game_change| game_change.game.uid)
+            .filter(|game_id| {
+                let old_keys = old.get_game_by_id(*game_id).map(MetadataKeys::from);
+                let new_keys = new.get_game_by_id(*game_id).map(MetadataKeys::from);
+                old_keys.ne(&new_keys)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{test_db, test_games};
+    use libpobsd::{SearchType, Status};
+
+    #[test]
+    fn test_diff_identical_databases() {
+        let changelog = ChangeLog::diff(&test_db(), &test_db());
+        assert!(changelog.is_empty());
+    }
+    #[test]
+    fn test_diff_added_removed_and_changed() {
+        let old = test_db();
+        let mut games = test_games();
+        let removed = games.remove(0);
+        let game = games
+            .iter_mut()
+            .find(|game| game.name.eq("The Adventures of Mr. Hat"))
+            .unwrap();
+        game.engine = Some("FNA".into());
+        game.status.status = Status::Completable;
+        game.igdb_id = None;
+        let mut added = Game::new();
+        added.name = "A new game".into();
+        added.uid = 42;
+        games.push(added);
+        let new = GameDataBase::new(games);
+
+        let changelog = ChangeLog::diff(&old, &new);
+        assert_eq!(
+            changelog.added,
+            vec![GameRef {
+                uid: 42,
+                name: "A new game".into()
+            }]
+        );
+        assert_eq!(changelog.removed, vec![GameRef::from(&removed)]);
+        assert_eq!(changelog.changed.len(), 1);
+        let change = &changelog.changed[0];
+        let game = new
+            .get_game_by_name("The Adventures of Mr. Hat", &SearchType::CaseSensitive)
+            .unwrap();
+        assert_eq!(change.game, GameRef::from(game));
+        assert_eq!(
+            change.changes[0],
+            FieldChange {
+                field: "engine",
+                old: Some("godot".into()),
+                new: Some("FNA".into()),
+            }
+        );
+        assert!(change.has_field("status"));
+        assert!(change.has_field("igdb_id"));
+        assert!(!change.has_field("runtime"));
+    }
+    #[test]
+    fn test_changed_keys() {
+        let old = test_db();
+        let mut games = test_games();
+        let engine_changed = games
+            .iter_mut()
+            .find(|game| game.name.eq("The Adventures of Mr. Hat"))
+            .unwrap();
+        engine_changed.engine = Some("FNA".into());
+        let engine_changed = engine_changed.uid;
+        let igdb_changed = games
+            .iter_mut()
+            .find(|game| game.igdb_id.is_some() && game.uid != engine_changed)
+            .unwrap();
+        igdb_changed.igdb_id = Some(1);
+        let igdb_changed = igdb_changed.uid;
+        let new = GameDataBase::new(games);
+
+        let changelog = ChangeLog::diff(&old, &new);
+        assert_eq!(changelog.changed.len(), 2);
+        assert_eq!(
+            changelog.changed_keys(&old, &new),
+            HashSet::from([igdb_changed])
+        );
+    }
+}
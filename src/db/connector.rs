@@ -1,51 +1,70 @@
+use arc_swap::ArcSwap;
 use libpobsd::GameDataBase;
+use std::sync::Arc;
 use tokio::sync::{
     mpsc::Sender as MpscSender,
     oneshot::{self, Receiver as OsReceiver},
 };
 
 use crate::{
-    db::{data::GameMetaData, requests::AppDbRequest, responses::AppDbResponse},
-    utils::GameFilterWrapper,
+    app::config::{MetadataConfig, PaginationConfig},
+    db::{
+        query::Field,
+        requests::AppDbRequest,
+        responses::AppDbResponse,
+        snapshot::{GameSelection, Snapshot},
+    },
+    provider::{FetchErrorKind, FetchedMetaData, MetadataKeys},
+    utils::DbState,
 };
 
-#[derive(Debug, Clone)]
+// Reads are answered from the last snapshot published
+// by the AppDb, writes are sent to the AppDb
+#[derive(Clone)]
 pub struct DbCon {
-    pub tx_read: MpscSender<AppDbRequest>,
+    pub snapshot: Arc<ArcSwap<Snapshot>>,
     pub tx_write: MpscSender<AppDbRequest>,
 }
 
 impl DbCon {
-    pub fn new(tx_read: MpscSender<AppDbRequest>, tx_write: MpscSender<AppDbRequest>) -> Self {
-        Self { tx_read, tx_write }
+    pub fn new(snapshot: Arc<ArcSwap<Snapshot>>, tx_write: MpscSender<AppDbRequest>) -> Self {
+        Self { snapshot, tx_write }
     }
     pub async fn get_game(&self, game_id: u32) -> AppDbResponse {
-        let (ack, rx) = oneshot::channel::<AppDbResponse>();
-        self.send(AppDbRequest::GetGame { game_id, ack }, rx).await
+        self.snapshot.load().get_game_by_id(game_id)
     }
     pub async fn get_game_stats(&self) -> AppDbResponse {
-        let (ack, rx) = oneshot::channel::<AppDbResponse>();
-        self.send(AppDbRequest::GetGameStats { ack }, rx).await
+        self.snapshot.load().get_game_stats()
     }
     pub async fn get_all_games(&self) -> AppDbResponse {
-        let (ack, rx) = oneshot::channel::<AppDbResponse>();
-        self.send(AppDbRequest::GetAllGames { ack }, rx).await
+        self.snapshot.load().get_all_games()
     }
+    // Missing or stale metadata are requested from the AppDb
+    // without waiting, the snapshot is answered right away
     pub async fn get_game_representation(&self, game_id: u32) -> AppDbResponse {
-        let (ack, rx) = oneshot::channel::<AppDbResponse>();
-        self.send(AppDbRequest::GetGameRepresentation { game_id, ack }, rx)
-            .await
+        let snapshot = self.snapshot.load();
+        if let Some(high_priority) = snapshot.metadata_to_fetch(game_id) {
+            let _ = self.tx_write.try_send(AppDbRequest::FetchMetadata {
+                game_id,
+                high_priority,
+            });
+        }
+        snapshot.get_game_representation_by_id(game_id)
     }
     pub async fn get_game_list(&self, game_ids: Vec<u32>) -> AppDbResponse {
-        let (ack, rx) = oneshot::channel::<AppDbResponse>();
-        self.send(AppDbRequest::GetGameList { game_ids, ack }, rx)
-            .await
+        self.snapshot.load().get_game_list(game_ids)
     }
-    pub async fn insert_metadata(&self, game_id: u32, metadata: GameMetaData) -> AppDbResponse {
+    pub async fn insert_metadata(
+        &self,
+        game_id: u32,
+        keys: MetadataKeys,
+        metadata: FetchedMetaData,
+    ) -> AppDbResponse {
         let (ack, rx) = oneshot::channel::<AppDbResponse>();
         self.send(
             AppDbRequest::InsertMetadata {
                 game_id,
+                keys,
                 metadata,
                 ack,
             },
@@ -53,49 +72,119 @@ impl DbCon {
         )
         .await
     }
-    pub async fn get_game_list_paginated_filtered(
+    pub async fn record_fetch_failure(
         &self,
-        page: usize,
-        filter: GameFilterWrapper,
+        game_id: u32,
+        keys: MetadataKeys,
+        kind: FetchErrorKind,
     ) -> AppDbResponse {
         let (ack, rx) = oneshot::channel::<AppDbResponse>();
         self.send(
-            AppDbRequest::GetGameListPaginatedFiltered { page, filter, ack },
+            AppDbRequest::RecordFetchFailure {
+                game_id,
+                keys,
+                kind,
+                ack,
+            },
             rx,
         )
         .await
     }
+    pub async fn get_game_list_paginated(
+        &self,
+        selection: &GameSelection,
+        page: usize,
+        per_page: Option<usize>,
+    ) -> AppDbResponse {
+        self.snapshot
+            .load()
+            .get_game_list_paginated(selection, page, per_page)
+    }
+    pub async fn get_facets(&self, selection: &GameSelection) -> AppDbResponse {
+        self.snapshot.load().get_facets(selection)
+    }
+    pub async fn get_facet(&self, field: Field) -> AppDbResponse {
+        self.snapshot.load().get_facet(field)
+    }
+    pub async fn get_entity(&self, field: Field, name: &str) -> AppDbResponse {
+        self.snapshot.load().get_entity(field, name)
+    }
+    pub async fn get_suggestions(&self, query: &str) -> AppDbResponse {
+        self.snapshot.load().get_suggestions(query)
+    }
     pub async fn update_db(&self, game_db: GameDataBase) -> AppDbResponse {
         let (ack, rx) = oneshot::channel::<AppDbResponse>();
-        self.send(AppDbRequest::UpdateDb { game_db, ack }, rx).await
+        self.send(
+            AppDbRequest::UpdateDb {
+                game_db: Box::new(game_db),
+                ack,
+            },
+            rx,
+        )
+        .await
     }
-    pub async fn get_random_uid(&self) -> AppDbResponse {
+    pub async fn update_db_state(&self, db_state: DbState) -> AppDbResponse {
         let (ack, rx) = oneshot::channel::<AppDbResponse>();
-        self.send(AppDbRequest::GetRandomUid { ack }, rx).await
+        self.send(AppDbRequest::UpdateDbState { db_state, ack }, rx)
+            .await
     }
-    pub async fn get_recent_games(&self) -> AppDbResponse {
+    pub async fn update_metadata_config(
+        &self,
+        metadata_config: MetadataConfig,
+        metadata_max_age: u64,
+    ) -> AppDbResponse {
         let (ack, rx) = oneshot::channel::<AppDbResponse>();
-        self.send(AppDbRequest::GetRecentGames { ack }, rx).await
+        self.send(
+            AppDbRequest::UpdateMetadataConfig {
+                metadata_config,
+                metadata_max_age,
+                ack,
+            },
+            rx,
+        )
+        .await
     }
-    pub async fn get_game_ids(&self) -> AppDbResponse {
+    pub async fn update_pagination_config(
+        &self,
+        pagination_config: PaginationConfig,
+    ) -> AppDbResponse {
         let (ack, rx) = oneshot::channel::<AppDbResponse>();
-        self.send(AppDbRequest::GetGameIds { ack }, rx).await
+        self.send(
+            AppDbRequest::UpdatePaginationConfig {
+                pagination_config,
+                ack,
+            },
+            rx,
+        )
+        .await
+    }
+    pub async fn get_db_state(&self) -> AppDbResponse {
+        self.snapshot.load().get_db_state()
+    }
+    pub async fn get_random_uid(&self) -> AppDbResponse {
+        self.snapshot.load().get_random_uid()
+    }
+    pub async fn get_recent_games(&self) -> AppDbResponse {
+        self.snapshot.load().get_recent_games()
+    }
+    pub async fn get_changelog(&self) -> AppDbResponse {
+        self.snapshot.load().get_changelog()
+    }
+    pub async fn get_game_ids(&self) -> AppDbResponse {
+        self.snapshot.load().get_game_ids()
+    }
+    pub async fn get_metadata_cache(&self) -> AppDbResponse {
+        self.snapshot.load().get_metadata_cache()
+    }
+    pub async fn get_fresh_metadata_ids(&self) -> AppDbResponse {
+        self.snapshot.load().get_fresh_metadata_ids()
     }
     pub async fn send(
         &self,
         app_db_request: AppDbRequest,
         rx: OsReceiver<AppDbResponse>,
     ) -> AppDbResponse {
-        let tx = match app_db_request {
-            AppDbRequest::InsertMetadata {
-                game_id: _,
-                metadata: _,
-                ack: _,
-            }
-            | AppDbRequest::UpdateDb { game_db: _, ack: _ } => self.tx_write.clone(),
-            _ => self.tx_read.clone(),
-        };
-        match tx.send(app_db_request).await {
+        match self.tx_write.send(app_db_request).await {
             Ok(_) => match rx.await {
                 Ok(app_db_response) => app_db_response,
                 Err(_) => AppDbResponse::Error,
@@ -1,5 +1,6 @@
 use std::fmt::Debug;
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -8,6 +9,31 @@ pub struct Metacritic {
     pub url: String,
 }
 
+// Where a metadata field comes from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Source {
+    pub provider: String,
+    pub fetched_at: DateTime<Utc>,
+}
+
+impl Source {
+    pub fn new(provider: &str) -> Self {
+        Self {
+            provider: provider.into(),
+            fetched_at: Utc::now(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub summary: Option<Source>,
+    pub story_line: Option<Source>,
+    pub screenshots: Option<Source>,
+    pub cover: Option<Source>,
+    pub metacritic: Option<Source>,
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct GameMetaData {
     pub summary: Option<String>,
@@ -15,25 +41,133 @@ pub struct GameMetaData {
     pub screenshots: Vec<String>,
     pub cover: Option<String>,
     pub metacritic: Option<Metacritic>,
+    // entries cached before provenance was tracked have none
+    #[serde(default)]
+    pub provenance: Provenance,
 }
 
 impl GameMetaData {
+    // Record the provider as the source of every field that is set
+    pub fn with_source(mut self, provider: &str) -> Self {
+        let source = Source::new(provider);
+        if self.summary.is_some() {
+            self.provenance.summary = Some(source.clone());
+        }
+        if self.story_line.is_some() {
+            self.provenance.story_line = Some(source.clone());
+        }
+        if !self.screenshots.is_empty() {
+            self.provenance.screenshots = Some(source.clone());
+        }
+        if self.cover.is_some() {
+            self.provenance.cover = Some(source.clone());
+        }
+        if self.metacritic.is_some() {
+            self.provenance.metacritic = Some(source);
+        }
+        self
+    }
+    // Only the fields that come from that provider
+    pub fn fields_from(&self, provider: &str) -> Self {
+        let from = |source: &Option<Source>| {
+            source
+                .as_ref()
+                .is_some_and(|source| source.provider == provider)
+        };
+        let provenance = &self.provenance;
+        let mut metadata = GameMetaData::default();
+        if from(&provenance.summary) {
+            metadata.summary = self.summary.clone();
+            metadata.provenance.summary = provenance.summary.clone();
+        }
+        if from(&provenance.story_line) {
+            metadata.story_line = self.story_line.clone();
+            metadata.provenance.story_line = provenance.story_line.clone();
+        }
+        if from(&provenance.screenshots) {
+            metadata.screenshots = self.screenshots.clone();
+            metadata.provenance.screenshots = provenance.screenshots.clone();
+        }
+        if from(&provenance.cover) {
+            metadata.cover = self.cover.clone();
+            metadata.provenance.cover = provenance.cover.clone();
+        }
+        if from(&provenance.metacritic) {
+            metadata.metacritic = self.metacritic.clone();
+            metadata.provenance.metacritic = provenance.metacritic.clone();
+        }
+        metadata
+    }
+    // Fields already set take precedence over the ones from other,
+    // so merging in provider order gives priority to the first providers.
+    // The provenance of each field follows the field.
     pub fn merge(mut self, other: GameMetaData) -> Self {
         if self.summary.is_none() {
             self.summary = other.summary;
+            self.provenance.summary = other.provenance.summary;
         }
         if self.story_line.is_none() {
             self.story_line = other.story_line;
+            self.provenance.story_line = other.provenance.story_line;
         }
-        if self.screenshots.len() < other.screenshots.len() {
+        if self.screenshots.is_empty() {
             self.screenshots = other.screenshots;
+            self.provenance.screenshots = other.provenance.screenshots;
         }
         if self.metacritic.is_none() {
             self.metacritic = other.metacritic;
+            self.provenance.metacritic = other.provenance.metacritic;
         }
         if self.cover.is_none() {
             self.cover = other.cover;
+            self.provenance.cover = other.provenance.cover;
         }
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_gives_priority_to_self() {
+        let first = GameMetaData {
+            summary: Some("first".into()),
+            screenshots: vec!["first.jpg".into()],
+            ..Default::default()
+        };
+        let second = GameMetaData {
+            summary: Some("second".into()),
+            story_line: Some("second".into()),
+            screenshots: vec!["second.jpg".into(), "second_bis.jpg".into()],
+            cover: Some("second.png".into()),
+            ..Default::default()
+        };
+        let merged = first.merge(second);
+        assert_eq!(merged.summary, Some("first".into()));
+        assert_eq!(merged.story_line, Some("second".into()));
+        assert_eq!(merged.screenshots, vec![String::from("first.jpg")]);
+        assert_eq!(merged.cover, Some("second.png".into()));
+    }
+    #[test]
+    fn test_provenance_follows_merged_fields() {
+        let first = GameMetaData {
+            summary: Some("first".into()),
+            ..Default::default()
+        }
+        .with_source("First");
+        let second = GameMetaData {
+            summary: Some("second".into()),
+            cover: Some("second.png".into()),
+            ..Default::default()
+        }
+        .with_source("Second");
+        let merged = first.merge(second);
+        let provider = |source: &Option<Source>| source.as_ref().map(|s| s.provider.clone());
+        assert_eq!(provider(&merged.provenance.summary), Some("First".into()));
+        assert_eq!(provider(&merged.provenance.cover), Some("Second".into()));
+        assert_eq!(provider(&merged.provenance.story_line), None);
+        assert_eq!(provider(&merged.provenance.metacritic), None);
+    }
+}
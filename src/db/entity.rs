@@ -0,0 +1,97 @@
+use libpobsd::{Game, GameDataBase};
+use std::collections::HashSet;
+
+use crate::db::{
+    facets::{Facet, FacetIndex},
+    query::Field,
+};
+
+// The fields shown beside the games of each kind of entity
+fn related_fields(field: Field) -> &'static [Field] {
+    match field {
+        Field::Engine => &[Field::Runtime],
+        Field::Runtime => &[Field::Engine],
+        Field::Dev => &[Field::Engine, Field::Runtime, Field::Publi],
+        Field::Publi => &[Field::Engine, Field::Runtime, Field::Dev],
+        _ => &[],
+    }
+}
+
+// An engine, a runtime, a developer or a publisher and its games
+#[derive(Debug, Clone)]
+pub struct Entity {
+    pub field: Field,
+    pub name: String,
+    pub games: Vec<Game>,
+    pub statuses: Option<Facet>,
+    // first and last years a game was released
+    pub years: Option<(u32, u32)>,
+    pub related: Vec<Facet>,
+}
+
+impl Entity {
+    // None when the field has no entity or no game has that value
+    pub fn new(games: &GameDataBase, index: &FacetIndex, field: Field, name: &str) -> Option<Self> {
+        let mut games = match field {
+            Field::Engine => games.match_games_by_engine(name),
+            Field::Runtime => games.match_games_by_runtime(name),
+            Field::Dev => games.match_games_by_dev(name),
+            Field::Publi => games.match_games_by_publi(name),
+            _ => return None,
+        }
+        .into_inner();
+        if games.is_empty() {
+            return None;
+        }
+        // in the order of the game list
+        games.sort();
+        let game_ids: HashSet<u32> = games.iter().map(|game| game.uid).collect();
+        let mut facets = index.facets(&game_ids);
+        let statuses = facets
+            .iter()
+            .position(|facet| facet.field == Field::Status)
+            .map(|position| facets.remove(position));
+        facets.retain(|facet| related_fields(field).contains(&facet.field));
+        // the year can be text such as "early access"
+        let years: Vec<u32> = games
+            .iter()
+            .filter_map(|game| game.year.as_deref()?.parse().ok())
+            .collect();
+        let years = years.iter().min().zip(years.iter().max());
+        Some(Self {
+            field,
+            name: name.into(),
+            games: games.into_iter().cloned().collect(),
+            statuses,
+            years: years.map(|(first, last)| (*first, *last)),
+            related: facets,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_db;
+
+    #[test]
+    fn test_engine_entity() {
+        let games = test_db();
+        let index = FacetIndex::new(&games);
+        let entity = Entity::new(&games, &index, Field::Engine, "FNA").unwrap();
+        let names: Vec<&str> = entity.games.iter().map(|game| game.name.as_str()).collect();
+        // the article is ignored
+        assert_eq!(names, vec!["The Adventures of Shuggy", "Aeternum"]);
+        assert_eq!(entity.years, Some((2012, 2017)));
+        let statuses = entity.statuses.unwrap();
+        let count: usize = statuses.values.iter().map(|value| value.count).sum();
+        assert_eq!(count, 2);
+        // only the runtimes are related to an engine
+        assert_eq!(entity.related.len(), 1);
+        assert_eq!(entity.related[0].field, Field::Runtime);
+        assert_eq!(entity.related[0].values[0].value, "fnaify");
+        // the name has to match exactly
+        assert!(Entity::new(&games, &index, Field::Engine, "fna").is_none());
+        assert!(Entity::new(&games, &index, Field::Genre, "RPG").is_none());
+    }
+}
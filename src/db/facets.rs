@@ -0,0 +1,205 @@
+use libpobsd::{GameDataBase, Status};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashSet},
+};
+
+use crate::db::query::Field;
+
+// number of values shown for each facet
+const FACET_SIZE: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct FacetValue {
+    pub value: String,
+    pub label: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Facet {
+    pub field: Field,
+    pub label: &'static str,
+    pub values: Vec<FacetValue>,
+}
+
+fn status_label(status: &Status) -> &'static str {
+    match status {
+        Status::Unknown => "Unknown",
+        Status::DoesNotRun => "Does not run",
+        Status::Launches => "Launches",
+        Status::MajorBugs => "Major bugs",
+        Status::MediumImpact => "Medium impact",
+        Status::MinorBugs => "Minor bugs",
+        Status::Completable => "Completable",
+        Status::Perfect => "Perfect",
+    }
+}
+
+// a value, its label and its games
+type IndexedValue = (String, String, Vec<u32>);
+
+// The games of each value of the fields, computed once
+// for each version of the game database
+#[derive(Debug, Clone, Default)]
+pub struct FacetIndex {
+    fields: Vec<(Field, &'static str, Vec<IndexedValue>)>,
+}
+
+impl FacetIndex {
+    pub fn new(games: &GameDataBase) -> Self {
+        let with_labels = |items: Vec<(String, Vec<u32>)>| -> Vec<IndexedValue> {
+            items
+                .into_iter()
+                .map(|(value, game_ids)| (value.clone(), value, game_ids))
+                .collect()
+        };
+        let mut statuses: BTreeMap<Status, Vec<u32>> = BTreeMap::new();
+        for game in games.get_all_games() {
+            statuses
+                .entry(game.status.status.clone())
+                .or_default()
+                .push(game.uid);
+        }
+        let statuses = statuses
+            .into_iter()
+            .map(|(status, game_ids)| (status.to_string(), status_label(&status).into(), game_ids))
+            .collect();
+        Self {
+            fields: vec![
+                (
+                    Field::Engine,
+                    "Engine",
+                    with_labels(games.get_all_engines_with_ids()),
+                ),
+                (
+                    Field::Runtime,
+                    "Runtime",
+                    with_labels(games.get_all_runtimes_with_ids()),
+                ),
+                (
+                    Field::Genre,
+                    "Genre",
+                    with_labels(games.get_all_genres_with_ids()),
+                ),
+                (
+                    Field::Tag,
+                    "Tag",
+                    with_labels(games.get_all_tags_with_ids()),
+                ),
+                (
+                    Field::Year,
+                    "Year",
+                    with_labels(games.get_all_years_with_ids()),
+                ),
+                (
+                    Field::Dev,
+                    "Developer",
+                    with_labels(games.get_all_devs_with_ids()),
+                ),
+                (
+                    Field::Publi,
+                    "Publisher",
+                    with_labels(games.get_all_publis_with_ids()),
+                ),
+                (Field::Status, "Status", statuses),
+            ],
+        }
+    }
+    // The most frequent values among the given games, facets
+    // without any value are left out
+    pub fn facets(&self, game_ids: &HashSet<u32>) -> Vec<Facet> {
+        self.fields
+            .iter()
+            .map(|(field, label, items)| {
+                let mut values: Vec<FacetValue> = items
+                    .iter()
+                    .map(|(value, label, ids)| FacetValue {
+                        value: value.clone(),
+                        label: label.clone(),
+                        count: ids.iter().filter(|id| game_ids.contains(id)).count(),
+                    })
+                    .filter(|value| value.count > 0)
+                    .collect();
+                // the sort is stable so values keep their order for a same count
+                values.sort_by_key(|value| Reverse(value.count));
+                values.truncate(FACET_SIZE);
+                Facet {
+                    field: *field,
+                    label,
+                    values,
+                }
+            })
+            .filter(|facet| !facet.values.is_empty())
+            .collect()
+    }
+    // Every value of a field with its number of games,
+    // in the order of the game database
+    pub fn facet(&self, field: Field) -> Option<Facet> {
+        self.fields
+            .iter()
+            .find(|(indexed_field, _, _)| *indexed_field == field)
+            .map(|(field, label, items)| Facet {
+                field: *field,
+                label,
+                values: items
+                    .iter()
+                    .map(|(value, label, ids)| FacetValue {
+                        value: value.clone(),
+                        label: label.clone(),
+                        count: ids.len(),
+                    })
+                    .collect(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_db;
+
+    #[test]
+    fn test_facets_count_the_given_games() {
+        let games = test_db();
+        let index = FacetIndex::new(&games);
+        let game_ids: HashSet<u32> = games
+            .get_all_games()
+            .into_iter()
+            .filter(|game| game.genres.iter().flatten().any(|genre| genre.eq("RPG")))
+            .map(|game| game.uid)
+            .collect();
+        let facets = index.facets(&game_ids);
+        let genre = facets
+            .iter()
+            .find(|facet| facet.field == Field::Genre)
+            .unwrap();
+        assert_eq!(genre.values.len(), 1);
+        assert_eq!(genre.values[0].value, "RPG");
+        assert_eq!(genre.values[0].count, 2);
+        let status = facets
+            .iter()
+            .find(|facet| facet.field == Field::Status)
+            .unwrap();
+        assert_eq!(status.values[0].label, "Unknown");
+        assert_eq!(status.values[0].count, 2);
+        // an empty selection has no facet
+        assert!(index.facets(&HashSet::new()).is_empty());
+    }
+    #[test]
+    fn test_facet_is_not_truncated() {
+        let games = test_db();
+        let index = FacetIndex::new(&games);
+        let engine = index.facet(Field::Engine).unwrap();
+        assert_eq!(engine.label, "Engine");
+        assert_eq!(engine.values.len(), games.get_all_engines().count);
+        let count: usize = engine.values.iter().map(|value| value.count).sum();
+        let with_engine = games
+            .get_all_games()
+            .into_iter()
+            .filter(|game| game.engine.is_some())
+            .count();
+        assert_eq!(count, with_engine);
+        assert!(index.facet(Field::Name).is_none());
+    }
+}
@@ -1,227 +1,430 @@
+pub mod changelog;
 pub mod connector;
 pub mod data;
+pub mod entity;
+pub mod facets;
+pub mod query;
 pub mod requests;
 pub mod responses;
+pub mod search;
+pub mod snapshot;
+pub mod sort;
 pub mod stats;
 
-use chrono::{Duration, Local};
-use libpobsd::{Game, GameDataBase, SearchType};
-use rand::prelude::*;
-use std::collections::{HashMap, HashSet};
+use arc_swap::ArcSwap;
+use libpobsd::GameDataBase;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+    time::Instant,
+};
 use tokio::sync::{
     mpsc::{self, Sender as MpscSender},
     oneshot::{self},
 };
 
 use crate::{
-    app::{pagination::Paginator, representation::GameRepresentation},
+    app::config::{MetadataConfig, PaginationConfig},
+    cache::{CachedMetaData, FailedLookup, MetaDataCache, MetaDataEntries},
     db::{
-        connector::DbCon, data::GameMetaData, requests::AppDbRequest, responses::AppDbResponse,
-        stats::GameStats,
+        changelog::{ChangeLog, CHANGELOG_SIZE},
+        connector::DbCon,
+        requests::AppDbRequest,
+        responses::AppDbResponse,
+        search::SearchIndex,
+        snapshot::{GameData, Snapshot},
     },
     fetcher::FetcherMessage,
-    utils::GameFilterWrapper,
+    provider::{FetchErrorKind, FetchResult, FetchedMetaData, MetadataKeys},
+    utils::DbState,
 };
 
+// a lookup that did not come back is
+// tried again after that delay, in seconds
+const IN_FLIGHT_TIMEOUT: u64 = 600;
+
+// The AppDb only handles the writes. Each write publishes a new
+// Snapshot that the DbCon reads from without going through the AppDb.
 pub struct AppDb {
-    stats: GameStats,
-    last_updated: String,
-    games: GameDataBase,
-    game_ids: HashSet<u32>,
-    game_metadata: HashMap<u32, GameMetaData>,
+    snapshot: Arc<ArcSwap<Snapshot>>,
     igdb_tx: MpscSender<FetcherMessage>,
+    // used to refresh stale metadata in the background
+    lptx: MpscSender<FetcherMessage>,
+    // games with a lookup in progress
+    in_flight: HashMap<u32, Instant>,
+    // used by the fetch tasks to insert the new metadata,
+    // set when the AppDb is launched
+    db_tx: Option<MpscSender<AppDbRequest>>,
 }
 
 impl AppDb {
-    pub fn new(games: GameDataBase, igdb_tx: MpscSender<FetcherMessage>) -> Self {
-        let game_ids: HashSet<u32> = games.get_all_games().into_iter().map(|g| g.uid).collect();
+    pub fn new(
+        games: GameDataBase,
+        metadata_cache: MetaDataCache,
+        metadata_max_age: u64,
+        metadata_config: MetadataConfig,
+        pagination_config: PaginationConfig,
+        igdb_tx: MpscSender<FetcherMessage>,
+        lptx: MpscSender<FetcherMessage>,
+    ) -> Self {
+        let game_metadata: MetaDataEntries = metadata_cache
+            .entries
+            .into_iter()
+            .map(|(game_id, cached)| (game_id, Arc::new(cached)))
+            .collect();
+        let search_index = SearchIndex::new(&games, &game_metadata);
+        let snapshot = Snapshot {
+            game_data: Arc::new(GameData::new(games)),
+            search_index: Arc::new(search_index),
+            game_metadata,
+            failed_lookups: metadata_cache.failures.into_iter().collect(),
+            metadata_config: Arc::new(metadata_config),
+            metadata_max_age,
+            pagination_config: Arc::new(pagination_config),
+            changelog: Arc::new(VecDeque::new()),
+            db_state: Arc::new(DbState::default()),
+        };
         Self {
-            games,
-            game_ids,
-            last_updated: chrono::Utc::now().to_rfc2822(),
-            game_metadata: HashMap::default(),
+            snapshot: Arc::new(ArcSwap::from_pointee(snapshot)),
             igdb_tx,
-            stats: GameStats::default(),
+            lptx,
+            in_flight: HashMap::new(),
+            db_tx: None,
         }
     }
-    pub fn game_has_metadata(&self, game_id: u32) -> bool {
-        match self.games.get_game_by_id(game_id) {
-            Some(game) => game.igdb_id.is_some() || game.get_steam_id().is_some(),
-            None => false,
-        }
+    // Apply a change to a copy of the current snapshot and publish it
+    fn publish(&self, change: impl FnOnce(&mut Snapshot)) {
+        let mut snapshot = Snapshot::clone(&self.snapshot.load());
+        change(&mut snapshot);
+        self.snapshot.store(Arc::new(snapshot));
     }
-    pub fn get_game_by_id(&self, game_id: u32) -> AppDbResponse {
-        match self.games.get_game_by_id(game_id) {
-            Some(game) => AppDbResponse::Game(game.clone()),
-            None => AppDbResponse::NoGame,
+    fn is_in_flight(&self, game_id: u32) -> bool {
+        match self.in_flight.get(&game_id) {
+            Some(started) => started.elapsed().as_secs() < IN_FLIGHT_TIMEOUT,
+            None => false,
         }
     }
-    pub fn get_game_stats(&self) -> AppDbResponse {
-        AppDbResponse::GameStats(self.stats.clone())
-    }
-    pub fn get_all_games(&self) -> AppDbResponse {
-        let games: Vec<Game> = self.games.get_all_games().into_iter().cloned().collect();
-        AppDbResponse::AllGames(games)
-    }
-    pub async fn get_game_representation_by_id(&self, game_id: u32) -> AppDbResponse {
-        match self.get_game_by_id(game_id) {
-            AppDbResponse::Game(game) => {
-                if self.game_has_metadata(game_id) {
-                    match self.game_metadata.get(&game_id) {
-                        Some(metadata) => {
-                            let gr = GameRepresentation {
-                                game: game.clone(),
-                                metadata: Some(metadata.clone()),
-                            };
-                            AppDbResponse::GameRepresentation(gr)
-                        }
-                        None => {
-                            let (tx, rx) = oneshot::channel::<GameMetaData>();
-                            let _ = self
-                                .igdb_tx
-                                .send(FetcherMessage {
-                                    igdb_id: game.igdb_id,
-                                    steam_id: game.get_steam_id(),
-                                    game_name: game.name.clone(),
-                                    sender: tx,
-                                })
-                                .await;
-                            AppDbResponse::Pending(rx)
-                        }
-                    }
-                } else {
-                    let gr = GameRepresentation {
-                        game: game.clone(),
-                        metadata: None,
-                    };
-                    AppDbResponse::GameRepresentation(gr)
-                }
-            }
-            AppDbResponse::NoGame => AppDbResponse::NoGame,
-            _ => unreachable!("Other variants cannot be returned by get_game_by_id"),
+    // Queue a fetch without waiting for the fetcher, the result
+    // is inserted back in the AppDb or recorded as a failure
+    fn fetch_metadata(&mut self, game_id: u32, high_priority: bool) {
+        // readers ask again until the metadata are there,
+        // the state may have changed since they did
+        if self.is_in_flight(game_id) {
+            return;
         }
-    }
-    pub fn get_game_list(&self, game_ids: Vec<u32>) -> AppDbResponse {
-        let mut games: Vec<Game> = vec![];
-        for game_id in game_ids {
-            if let Some(game) = self.games.get_game_by_id(game_id) {
-                games.push(game.clone());
-            }
+        let snapshot = self.snapshot.load_full();
+        if snapshot.metadata_to_fetch(game_id).is_none() {
+            return;
         }
-        AppDbResponse::GameList(games)
-    }
-    pub fn get_game_list_paginated_filtered(
-        &self,
-        page: usize,
-        filter: GameFilterWrapper,
-    ) -> AppDbResponse {
-        let games: Vec<Game> = if filter.is_empty() {
-            self.games.get_all_games().into_iter().cloned().collect()
+        let Some(game) = snapshot.game_data.games.get_game_by_id(game_id) else {
+            return;
+        };
+        let Some(db_tx) = self.db_tx.clone() else {
+            return;
+        };
+        let keys = MetadataKeys::from(game);
+        let (tx, rx) = oneshot::channel::<FetchResult>();
+        let fetcher_message = FetcherMessage {
+            keys: keys.clone(),
+            sender: tx,
+        };
+        let fetcher_tx = if high_priority {
+            &self.igdb_tx
         } else {
-            self.games
-                .search_game_by_filter(&SearchType::NotCaseSensitive, &filter)
-                .into_iter()
-                .cloned()
-                .collect()
+            &self.lptx
         };
-        match Paginator::new(games.len(), 15).page(page) {
-            Some(page) => AppDbResponse::GameListPaginated(
-                games[page.first_element..=page.last_element].to_vec(),
-                page,
-            ),
-            // It can be None because of an error or the list of games
-            // is empty and the Paginator returns None
-            None => {
-                if games.is_empty() {
-                    AppDbResponse::NoGame
-                } else {
-                    AppDbResponse::Error
+        if let Err(e) = fetcher_tx.try_send(fetcher_message) {
+            tracing::debug!("Could not queue metadata fetch for {}: {e}", game_id);
+            return;
+        }
+        if !high_priority {
+            tracing::debug!("Refreshing stale metadata for {}", game_id);
+        }
+        self.in_flight.insert(game_id, Instant::now());
+        tokio::spawn(async move {
+            let (ack, _) = oneshot::channel::<AppDbResponse>();
+            match rx.await {
+                Ok(Ok(metadata)) => {
+                    let _ = db_tx
+                        .send(AppDbRequest::InsertMetadata {
+                            game_id,
+                            keys,
+                            metadata,
+                            ack,
+                        })
+                        .await;
+                }
+                Ok(Err(kind)) => {
+                    let _ = db_tx
+                        .send(AppDbRequest::RecordFetchFailure {
+                            game_id,
+                            keys,
+                            kind,
+                            ack,
+                        })
+                        .await;
                 }
+                // the fetcher is gone, the game stays in flight until it times out
+                Err(_) => {}
             }
+        });
+    }
+    // The result of a lookup made with other keys than the current
+    // ones of the game, or for a game that is gone, is outdated
+    fn is_outdated(&self, game_id: u32, keys: &MetadataKeys) -> bool {
+        match self.snapshot.load().game_data.games.get_game_by_id(game_id) {
+            Some(game) => MetadataKeys::from(game) != *keys,
+            None => true,
         }
     }
-    pub fn get_recent_games(&self) -> AppDbResponse {
-        let now = Local::now().naive_local().date();
-        let mut games: Vec<&Game> = self
-            .games
-            .get_all_games()
-            .into_iter()
-            .filter(|a| now - a.added < Duration::try_days(30).unwrap())
-            .collect();
-        games.sort_by(|a, b| a.added.partial_cmp(&b.added).unwrap());
-        games.reverse();
-        let mut grs: Vec<GameRepresentation> = vec![];
-        for game in games {
-            grs.push(GameRepresentation {
-                game: game.clone(),
-                metadata: self.game_metadata.get(&game.uid).cloned(),
-            })
+    // A lookup in flight for the current keys is left alone
+    // when the result of an outdated one comes back
+    pub fn insert_metadata(&mut self, game_id: u32, keys: &MetadataKeys, fetched: FetchedMetaData) {
+        if self.is_outdated(game_id, keys) {
+            tracing::debug!("Dropping outdated metadata for {}", game_id);
+            return;
+        }
+        self.in_flight.remove(&game_id);
+        // a stale entry keeps what the providers that failed gave before
+        let previous = self.snapshot.load().game_metadata.get(&game_id).cloned();
+        let metadata = fetched.metadata(previous.as_ref().map(|cached| &cached.metadata));
+        for failure in fetched.failures() {
+            tracing::debug!(
+                "{} failed the metadata lookup for {} ({})",
+                failure.provider,
+                game_id,
+                failure.kind
+            );
         }
-        AppDbResponse::RecentGames(grs)
+        let cached = CachedMetaData::new(metadata.clone()).with_failures(fetched.failures());
+        self.publish(|snapshot| {
+            let mut search_index = snapshot.search_index.as_ref().clone();
+            search_index.index_metadata(game_id, &metadata);
+            snapshot.search_index = Arc::new(search_index);
+            snapshot.game_metadata.insert(game_id, Arc::new(cached));
+            snapshot.failed_lookups.remove(&game_id);
+        });
+    }
+    pub fn record_fetch_failure(
+        &mut self,
+        game_id: u32,
+        keys: &MetadataKeys,
+        kind: FetchErrorKind,
+    ) {
+        if self.is_outdated(game_id, keys) {
+            tracing::debug!("Dropping outdated metadata lookup failure for {}", game_id);
+            return;
+        }
+        self.in_flight.remove(&game_id);
+        self.publish(|snapshot| {
+            let failed_lookup = snapshot
+                .failed_lookups
+                .entry(game_id)
+                .and_modify(|failed_lookup| failed_lookup.record(kind))
+                .or_insert_with(|| FailedLookup::new(kind));
+            tracing::debug!(
+                "Metadata lookup for {} failed ({}) {} time(s), next try on {}",
+                game_id,
+                kind,
+                failed_lookup.attempts,
+                failed_lookup.next_retry
+            );
+        });
+    }
+    // Return the games whose metadata were dropped because
+    // the keys used to look them up have changed
+    pub fn update_db(&mut self, game_db: GameDataBase) -> HashSet<u32> {
+        let old_game_data = self.snapshot.load().game_data.clone();
+        let changelog = ChangeLog::diff(&old_game_data.games, &game_db);
+        let invalidated = changelog.changed_keys(&old_game_data.games, &game_db);
+        let game_data = Arc::new(GameData::new(game_db));
+        tracing::info!(
+            "Game database updated: {} added, {} removed, {} changed",
+            changelog.added.len(),
+            changelog.removed.len(),
+            changelog.changed.len()
+        );
+        for game_id in &invalidated {
+            tracing::debug!(
+                "Metadata keys of {} changed, dropping its metadata",
+                game_id
+            );
+            // a lookup still running uses the old keys
+            self.in_flight.remove(game_id);
+        }
+        // nothing is kept for the games that are gone
+        let removed: Vec<u32> = changelog.removed.iter().map(|game| game.uid).collect();
+        for game_id in &removed {
+            self.in_flight.remove(game_id);
+        }
+        self.publish(|snapshot| {
+            for game_id in invalidated.iter().chain(&removed) {
+                snapshot.game_metadata.remove(game_id);
+                snapshot.failed_lookups.remove(game_id);
+            }
+            let search_index = SearchIndex::new(&game_data.games, &snapshot.game_metadata);
+            snapshot.search_index = Arc::new(search_index);
+            snapshot.game_data = game_data;
+            if !changelog.is_empty() {
+                let mut history = snapshot.changelog.as_ref().clone();
+                history.push_front(changelog);
+                history.truncate(CHANGELOG_SIZE);
+                snapshot.changelog = Arc::new(history);
+            }
+        });
+        invalidated
+    }
+    pub fn update_db_state(&mut self, db_state: DbState) {
+        let db_state = Arc::new(db_state);
+        self.publish(|snapshot| snapshot.db_state = db_state);
+    }
+    // Freshness is checked against the new settings from now on
+    pub fn update_metadata_config(&mut self, metadata_config: MetadataConfig, max_age: u64) {
+        let metadata_config = Arc::new(metadata_config);
+        self.publish(|snapshot| {
+            snapshot.metadata_config = metadata_config;
+            snapshot.metadata_max_age = max_age;
+        });
+    }
+    pub fn update_pagination_config(&mut self, pagination_config: PaginationConfig) {
+        let pagination_config = Arc::new(pagination_config);
+        self.publish(|snapshot| snapshot.pagination_config = pagination_config);
     }
     pub fn launch(mut self) -> DbCon {
-        self.update_stats();
-        let (tx_read, mut rx_read) = mpsc::channel::<AppDbRequest>(150);
         let (tx_write, mut rx_write) = mpsc::channel::<AppDbRequest>(150);
+        self.db_tx = Some(tx_write.clone());
+        let snapshot = self.snapshot.clone();
         tokio::spawn(async move {
-            loop {
-                let adbr: AppDbRequest = tokio::select! {
-                    Some(abr) = rx_write.recv() => {
-                        abr
-                    }
-                    Some(abr) = rx_read.recv() => {
-                       abr
-                    }
-                };
+            while let Some(adbr) = rx_write.recv().await {
                 match adbr {
-                    AppDbRequest::GetGame { game_id, ack } => {
-                        let _ = ack.send(self.get_game_by_id(game_id));
-                    }
-                    AppDbRequest::GetGameIds { ack } => {
-                        let game_ids = self.game_ids.clone();
-                        let _ = ack.send(AppDbResponse::GameIds(game_ids));
-                    }
-                    AppDbRequest::GetGameStats { ack } => {
-                        let _ = ack.send(self.get_game_stats());
-                    }
-                    AppDbRequest::GetAllGames { ack } => {
-                        let _ = ack.send(self.get_all_games());
-                    }
-                    AppDbRequest::GetGameList { game_ids, ack } => {
-                        let _ = ack.send(self.get_game_list(game_ids));
-                    }
-                    AppDbRequest::GetGameListPaginatedFiltered { page, filter, ack } => {
-                        let _ = ack.send(self.get_game_list_paginated_filtered(page, filter));
-                    }
-                    AppDbRequest::GetGameRepresentation { game_id, ack } => {
-                        let _ = ack.send(self.get_game_representation_by_id(game_id).await);
-                    }
-                    AppDbRequest::GetRandomUid { ack } => {
-                        let games = self.games.get_all_games();
-                        let game_number = games.count;
-                        let rid = thread_rng().gen_range(0..game_number);
-                        let _ = ack.send(AppDbResponse::RandomUid(games.into_inner()[rid].uid));
-                    }
-                    AppDbRequest::GetRecentGames { ack } => {
-                        let _ = ack.send(self.get_recent_games());
+                    AppDbRequest::FetchMetadata {
+                        game_id,
+                        high_priority,
+                    } => {
+                        self.fetch_metadata(game_id, high_priority);
                     }
                     AppDbRequest::InsertMetadata {
                         game_id,
+                        keys,
                         metadata,
                         ack,
                     } => {
-                        self.game_metadata.insert(game_id, metadata);
+                        self.insert_metadata(game_id, &keys, metadata);
+                        let _ = ack.send(AppDbResponse::Done);
+                    }
+                    AppDbRequest::RecordFetchFailure {
+                        game_id,
+                        keys,
+                        kind,
+                        ack,
+                    } => {
+                        self.record_fetch_failure(game_id, &keys, kind);
                         let _ = ack.send(AppDbResponse::Done);
                     }
                     AppDbRequest::UpdateDb { game_db, ack } => {
-                        self.games = game_db;
-                        self.update_stats();
+                        let invalidated = self.update_db(*game_db);
+                        let _ = ack.send(AppDbResponse::GameIds(invalidated));
+                    }
+                    AppDbRequest::UpdateDbState { db_state, ack } => {
+                        self.update_db_state(db_state);
+                        let _ = ack.send(AppDbResponse::Done);
+                    }
+                    AppDbRequest::UpdateMetadataConfig {
+                        metadata_config,
+                        metadata_max_age,
+                        ack,
+                    } => {
+                        self.update_metadata_config(metadata_config, metadata_max_age);
+                        let _ = ack.send(AppDbResponse::Done);
+                    }
+                    AppDbRequest::UpdatePaginationConfig {
+                        pagination_config,
+                        ack,
+                    } => {
+                        self.update_pagination_config(pagination_config);
                         let _ = ack.send(AppDbResponse::Done);
-                        self.last_updated = chrono::Utc::now().to_rfc2822();
                     }
                 }
             }
         });
-        DbCon { tx_read, tx_write }
+        DbCon::new(snapshot, tx_write)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        db::data::GameMetaData,
+        test_utils::{game_id, test_db, test_games},
+    };
+
+    fn get_app_db() -> AppDb {
+        let (tx, _) = mpsc::channel::<FetcherMessage>(1);
+        AppDb::new(
+            test_db(),
+            MetaDataCache::default(),
+            3600,
+            Default::default(),
+            Default::default(),
+            tx.clone(),
+            tx,
+        )
+    }
+
+    fn fetched(summary: &str) -> FetchedMetaData {
+        let metadata = GameMetaData {
+            summary: Some(summary.into()),
+            ..Default::default()
+        };
+        FetchedMetaData {
+            answers: vec![("IGDB", Ok(metadata.with_source("IGDB")))],
+        }
+    }
+
+    #[test]
+    fn test_outdated_lookups_are_dropped() {
+        let mut app_db = get_app_db();
+        let snapshot = app_db.snapshot.load_full();
+        let game_id = game_id(&snapshot.game_data.games, "Aeternum");
+        let game = snapshot.game_data.games.get_game_by_id(game_id).unwrap();
+        let keys = MetadataKeys::from(game);
+        let old_keys = MetadataKeys {
+            igdb_id: Some(1),
+            ..keys.clone()
+        };
+        app_db.insert_metadata(game_id, &old_keys, fetched("old"));
+        app_db.record_fetch_failure(game_id, &old_keys, FetchErrorKind::NotFound);
+        // the game is gone
+        app_db.insert_metadata(1, &keys, fetched("gone"));
+        let snapshot = app_db.snapshot.load_full();
+        assert!(snapshot.game_metadata.is_empty());
+        assert!(snapshot.failed_lookups.is_empty());
+        app_db.insert_metadata(game_id, &keys, fetched("current"));
+        let cached = app_db.snapshot.load().game_metadata[&game_id].clone();
+        assert_eq!(cached.metadata.summary, Some("current".into()));
+    }
+    #[test]
+    fn test_removed_games_are_pruned() {
+        let mut app_db = get_app_db();
+        let mut games = test_games();
+        let removed = games.remove(0);
+        let kept = &games[0];
+        app_db.insert_metadata(removed.uid, &MetadataKeys::from(&removed), fetched("gone"));
+        app_db.record_fetch_failure(
+            kept.uid,
+            &MetadataKeys::from(kept),
+            FetchErrorKind::NotFound,
+        );
+        assert!(app_db
+            .snapshot
+            .load()
+            .game_metadata
+            .contains_key(&removed.uid));
+        let kept = kept.uid;
+        app_db.update_db(GameDataBase::new(games));
+        let snapshot = app_db.snapshot.load_full();
+        assert!(snapshot.game_metadata.is_empty());
+        assert!(snapshot.failed_lookups.contains_key(&kept));
     }
 }
@@ -0,0 +1,711 @@
+use libpobsd::{Game, GameFilter, SearchType, Status};
+use std::fmt::Display;
+
+// Small query language to filter the games, for instance
+// genre=RPG AND runtime IN (FNA, HashLink) AND status >= completable
+// NOT tag=multiplayer
+// = looks for the value in the field, == for the exact value.
+// AND binds tighter than OR and can be left out between two terms,
+// values with spaces or special characters are written between quotes,
+// a quote or a backslash within quotes is escaped with a backslash.
+
+const STATUSES: [Status; 8] = [
+    Status::Unknown,
+    Status::DoesNotRun,
+    Status::Launches,
+    Status::MajorBugs,
+    Status::MediumImpact,
+    Status::MinorBugs,
+    Status::Completable,
+    Status::Perfect,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Name,
+    Engine,
+    Runtime,
+    Genre,
+    Tag,
+    Year,
+    Dev,
+    Publi,
+    Status,
+}
+
+const FIELDS: [Field; 9] = [
+    Field::Name,
+    Field::Engine,
+    Field::Runtime,
+    Field::Genre,
+    Field::Tag,
+    Field::Year,
+    Field::Dev,
+    Field::Publi,
+    Field::Status,
+];
+
+impl Field {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Field::Name => "name",
+            Field::Engine => "engine",
+            Field::Runtime => "runtime",
+            Field::Genre => "genre",
+            Field::Tag => "tag",
+            Field::Year => "year",
+            Field::Dev => "dev",
+            Field::Publi => "publi",
+            Field::Status => "status",
+        }
+    }
+    fn from_name(name: &str) -> Option<Self> {
+        FIELDS
+            .into_iter()
+            .find(|field| field.as_str().eq_ignore_ascii_case(name))
+    }
+    // only those can be compared with <, <=, > and >=
+    fn is_ordered(&self) -> bool {
+        matches!(self, Field::Year | Field::Status)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Exact,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+}
+
+impl Op {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Op::Eq => "=",
+            Op::Exact => "==",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::In => "IN",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameQuery {
+    Compare {
+        field: Field,
+        op: Op,
+        values: Vec<String>,
+    },
+    Not(Box<GameQuery>),
+    And(Vec<GameQuery>),
+    Or(Vec<GameQuery>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError(String);
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+fn error<T>(message: String) -> Result<T, QueryError> {
+    Err(QueryError(message))
+}
+
+fn parse_status(value: &str) -> Option<Status> {
+    STATUSES
+        .into_iter()
+        .find(|status| status.to_string().eq_ignore_ascii_case(value))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    LParen,
+    RParen,
+    Comma,
+    Op(Op),
+    Word(String),
+    Quoted(String),
+}
+
+impl Token {
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+            Token::Op(op) => write!(f, "{}", op.as_str()),
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Quoted(value) => write!(f, "\"{}\"", value),
+        }
+    }
+}
+
+const SPECIAL_CHARS: [char; 8] = ['(', ')', ',', '"', '=', '!', '<', '>'];
+const KEYWORDS: [&str; 4] = ["AND", "OR", "NOT", "IN"];
+
+fn lex(text: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens: Vec<Token> = vec![];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            '=' => match chars.next_if_eq(&'=') {
+                Some(_) => Token::Op(Op::Exact),
+                None => Token::Op(Op::Eq),
+            },
+            '!' | '<' | '>' => {
+                let or_equal = chars.next_if_eq(&'=').is_some();
+                match (c, or_equal) {
+                    ('!', true) => Token::Op(Op::Ne),
+                    ('!', false) => return error("Expected '=' after '!'".into()),
+                    ('<', true) => Token::Op(Op::Le),
+                    ('<', false) => Token::Op(Op::Lt),
+                    (_, true) => Token::Op(Op::Ge),
+                    (_, false) => Token::Op(Op::Gt),
+                }
+            }
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        // other backslashes are kept as they are
+                        Some('\\') => match chars.next_if(|c| *c == '"' || *c == '\\') {
+                            Some(c) => value.push(c),
+                            None => value.push('\\'),
+                        },
+                        Some(c) => value.push(c),
+                        None => return error("Missing closing quote".into()),
+                    }
+                }
+                Token::Quoted(value)
+            }
+            c => {
+                let mut word = String::from(c);
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace() && !SPECIAL_CHARS.contains(c))
+                {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+// parsing, displaying and matching a query recurse
+// at each NOT and parenthesis, deeper queries are refused
+const MAX_DEPTH: usize = 32;
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+    fn next_if_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek().is_some_and(|token| token.is_keyword(keyword));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+    fn enter(&mut self) -> Result<(), QueryError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return error("The query is nested too deeply".into());
+        }
+        Ok(())
+    }
+    fn starts_term(&self) -> bool {
+        match self.peek() {
+            Some(Token::LParen) => true,
+            Some(token @ Token::Word(_)) => !token.is_keyword("OR"),
+            _ => false,
+        }
+    }
+    fn parse_or(&mut self) -> Result<GameQuery, QueryError> {
+        let mut terms = vec![self.parse_and()?];
+        while self.next_if_keyword("OR") {
+            terms.push(self.parse_and()?);
+        }
+        Ok(GameQuery::or(terms))
+    }
+    fn parse_and(&mut self) -> Result<GameQuery, QueryError> {
+        let mut terms = vec![self.parse_not()?];
+        loop {
+            if self.next_if_keyword("AND") || self.starts_term() {
+                terms.push(self.parse_not()?);
+            } else {
+                break;
+            }
+        }
+        Ok(GameQuery::and(terms))
+    }
+    fn parse_not(&mut self) -> Result<GameQuery, QueryError> {
+        if self.next_if_keyword("NOT") {
+            self.enter()?;
+            let query = GameQuery::Not(Box::new(self.parse_not()?));
+            self.depth -= 1;
+            Ok(query)
+        } else {
+            self.parse_term()
+        }
+    }
+    fn parse_term(&mut self) -> Result<GameQuery, QueryError> {
+        match self.next() {
+            Some(Token::LParen) => {
+                self.enter()?;
+                let query = self.parse_or()?;
+                self.depth -= 1;
+                match self.next() {
+                    Some(Token::RParen) => Ok(query),
+                    _ => error("Missing closing parenthesis".into()),
+                }
+            }
+            Some(Token::Word(name)) => {
+                let Some(field) = Field::from_name(&name) else {
+                    let fields: Vec<&str> = FIELDS.iter().map(Field::as_str).collect();
+                    return error(format!(
+                        "Unknown field '{}', expected one of {}",
+                        name,
+                        fields.join(", ")
+                    ));
+                };
+                self.parse_comparison(field)
+            }
+            Some(token) => error(format!("Expected a field, found '{}'", token)),
+            None => error("Expected a field at the end of the query".into()),
+        }
+    }
+    fn parse_comparison(&mut self, field: Field) -> Result<GameQuery, QueryError> {
+        let (op, values) = match self.next() {
+            Some(Token::Op(op)) => (op, vec![self.parse_value(field)?]),
+            Some(token) if token.is_keyword("IN") => {
+                if self.next() != Some(Token::LParen) {
+                    return error(format!("Expected '(' after '{} IN'", field.as_str()));
+                }
+                let mut values = vec![self.parse_value(field)?];
+                loop {
+                    match self.next() {
+                        Some(Token::Comma) => values.push(self.parse_value(field)?),
+                        Some(Token::RParen) => break,
+                        _ => return error("Missing closing parenthesis".into()),
+                    }
+                }
+                (Op::In, values)
+            }
+            _ => return error(format!("Expected an operator after '{}'", field.as_str())),
+        };
+        if matches!(op, Op::Lt | Op::Le | Op::Gt | Op::Ge) {
+            if !field.is_ordered() {
+                return error(format!(
+                    "'{}' can only be used with year and status",
+                    op.as_str()
+                ));
+            }
+            if field == Field::Year && values[0].parse::<u32>().is_err() {
+                return error(format!("'{}' is not a year", values[0]));
+            }
+        }
+        Ok(GameQuery::Compare { field, op, values })
+    }
+    fn parse_value(&mut self, field: Field) -> Result<String, QueryError> {
+        let value = match self.next() {
+            Some(Token::Word(value)) | Some(Token::Quoted(value)) => value,
+            _ => return error(format!("Expected a value for '{}'", field.as_str())),
+        };
+        if field == Field::Status {
+            return match parse_status(&value) {
+                Some(status) => Ok(status.to_string()),
+                None => {
+                    let statuses: Vec<String> = STATUSES.iter().map(Status::to_string).collect();
+                    error(format!(
+                        "Unknown status '{}', expected one of {}",
+                        value,
+                        statuses.join(", ")
+                    ))
+                }
+            };
+        }
+        Ok(value)
+    }
+}
+
+fn year_cmp(game: &Game, op: Op, value: &str) -> bool {
+    let (Some(year), Ok(value)) = (
+        game.year
+            .as_deref()
+            .and_then(|year| year.parse::<u32>().ok()),
+        value.parse::<u32>(),
+    ) else {
+        return false;
+    };
+    match op {
+        Op::Lt => year < value,
+        Op::Le => year <= value,
+        Op::Gt => year > value,
+        _ => year >= value,
+    }
+}
+
+fn status_cmp(game: &Game, op: Op, value: &str) -> bool {
+    let Some(value) = parse_status(value) else {
+        return false;
+    };
+    let status = &game.status.status;
+    // games without a known status are only found with status=unknown
+    if *status == Status::Unknown {
+        return false;
+    }
+    match op {
+        Op::Lt => status < &value,
+        Op::Le => status <= &value,
+        Op::Gt => status > &value,
+        _ => status >= &value,
+    }
+}
+
+// Same matching as the filters of the query string,
+// a field contains the value whatever the case
+fn field_is(game: &Game, field: Field, value: &str) -> bool {
+    let search_type = &SearchType::NotCaseSensitive;
+    match field {
+        Field::Name => game.name_contains(value, search_type),
+        Field::Engine => game.engine_contains(value, search_type),
+        Field::Runtime => game.runtime_contains(value, search_type),
+        Field::Genre => game.genres_contains(value, search_type),
+        Field::Tag => game.tags_contains(value, search_type),
+        Field::Year => game.year_contains(value, search_type),
+        Field::Dev => game.devs_contains(value, search_type),
+        Field::Publi => game.publis_contains(value, search_type),
+        Field::Status => game.status.status.to_string().eq(value),
+    }
+}
+
+// The whole value of the field whatever the case,
+// any item for the fields holding a list
+fn field_equals(game: &Game, field: Field, value: &str) -> bool {
+    let value = value.to_lowercase();
+    let equals = |text: &String| text.to_lowercase().eq(&value);
+    let any_equals = |items: &Option<Vec<String>>| items.iter().flatten().any(equals);
+    match field {
+        Field::Name => equals(&game.name),
+        Field::Engine => game.engine.as_ref().is_some_and(equals),
+        Field::Runtime => game.runtime.as_ref().is_some_and(equals),
+        Field::Genre => any_equals(&game.genres),
+        Field::Tag => any_equals(&game.tags),
+        Field::Year => game.year.as_ref().is_some_and(equals),
+        Field::Dev => any_equals(&game.devs),
+        Field::Publi => any_equals(&game.publis),
+        Field::Status => game.status.status.to_string().eq(&value),
+    }
+}
+
+fn write_value(f: &mut std::fmt::Formatter<'_>, value: &str) -> std::fmt::Result {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c.is_whitespace() || SPECIAL_CHARS.contains(&c))
+        || KEYWORDS
+            .iter()
+            .any(|keyword| keyword.eq_ignore_ascii_case(value));
+    if needs_quotes {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        write!(f, "\"{}\"", value)
+    } else {
+        write!(f, "{}", value)
+    }
+}
+
+impl GameQuery {
+    pub fn parse(text: &str) -> Result<Self, QueryError> {
+        let mut parser = Parser {
+            tokens: lex(text)?,
+            position: 0,
+            depth: 0,
+        };
+        if parser.peek().is_none() {
+            return error("The query is empty".into());
+        }
+        let query = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(query),
+            Some(token) => error(format!("Unexpected '{}'", token)),
+        }
+    }
+    // Text typed in the search box is taken as a query
+    // when it uses an operator, even if it is invalid
+    pub fn is_query(text: &str) -> bool {
+        GameQuery::parse(text).is_ok() || text.contains(['=', '<', '>'])
+    }
+    // Nested terms of the same kind are merged so
+    // the query is displayed the way it was parsed
+    pub fn and(terms: Vec<GameQuery>) -> Self {
+        let mut merged: Vec<GameQuery> = vec![];
+        for term in terms {
+            match term {
+                GameQuery::And(terms) => merged.extend(terms),
+                term => merged.push(term),
+            }
+        }
+        if merged.len() == 1 {
+            merged.remove(0)
+        } else {
+            GameQuery::And(merged)
+        }
+    }
+    fn or(terms: Vec<GameQuery>) -> Self {
+        let mut merged: Vec<GameQuery> = vec![];
+        for term in terms {
+            match term {
+                GameQuery::Or(terms) => merged.extend(terms),
+                term => merged.push(term),
+            }
+        }
+        if merged.len() == 1 {
+            merged.remove(0)
+        } else {
+            GameQuery::Or(merged)
+        }
+    }
+    // The filters of the query string match a game when any of them does
+    pub fn from_filter(filter: &GameFilter) -> Option<Self> {
+        let fields = [
+            (Field::Name, &filter.name),
+            (Field::Engine, &filter.engine),
+            (Field::Runtime, &filter.runtime),
+            (Field::Genre, &filter.genre),
+            (Field::Tag, &filter.tag),
+            (Field::Year, &filter.year),
+            (Field::Dev, &filter.dev),
+            (Field::Publi, &filter.publi),
+        ];
+        let mut terms: Vec<GameQuery> = fields
+            .into_iter()
+            .filter_map(|(field, value)| {
+                value.as_ref().map(|value| GameQuery::Compare {
+                    field,
+                    op: Op::Eq,
+                    values: vec![value.clone()],
+                })
+            })
+            .collect();
+        if let Some(ref status) = filter.status {
+            terms.push(GameQuery::Compare {
+                field: Field::Status,
+                op: Op::Eq,
+                values: vec![status.to_string()],
+            });
+        }
+        if terms.is_empty() {
+            None
+        } else {
+            Some(GameQuery::or(terms))
+        }
+    }
+    // True if every game matched has to match the term
+    pub fn requires(&self, term: &GameQuery) -> bool {
+        match self {
+            GameQuery::And(terms) => terms.contains(term),
+            query => query == term,
+        }
+    }
+    pub fn matches(&self, game: &Game) -> bool {
+        match self {
+            GameQuery::Compare { field, op, values } => match op {
+                Op::Eq => field_is(game, *field, &values[0]),
+                Op::Exact => field_equals(game, *field, &values[0]),
+                Op::Ne => !field_is(game, *field, &values[0]),
+                Op::In => values.iter().any(|value| field_is(game, *field, value)),
+                op if *field == Field::Year => year_cmp(game, *op, &values[0]),
+                op => status_cmp(game, *op, &values[0]),
+            },
+            GameQuery::Not(query) => !query.matches(game),
+            GameQuery::And(queries) => queries.iter().all(|query| query.matches(game)),
+            GameQuery::Or(queries) => queries.iter().any(|query| query.matches(game)),
+        }
+    }
+}
+
+impl Display for GameQuery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameQuery::Compare { field, op, values } => {
+                write!(f, "{}", field.as_str())?;
+                if *op == Op::In {
+                    write!(f, " IN (")?;
+                    for (i, value) in values.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write_value(f, value)?;
+                    }
+                    write!(f, ")")
+                } else {
+                    write!(f, "{}", op.as_str())?;
+                    write_value(f, &values[0])
+                }
+            }
+            GameQuery::Not(query) => match query.as_ref() {
+                GameQuery::And(_) | GameQuery::Or(_) => write!(f, "NOT ({})", query),
+                query => write!(f, "NOT {}", query),
+            },
+            GameQuery::And(queries) => {
+                for (i, query) in queries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " AND ")?;
+                    }
+                    match query {
+                        GameQuery::Or(_) => write!(f, "({})", query)?,
+                        query => write!(f, "{}", query)?,
+                    }
+                }
+                Ok(())
+            }
+            GameQuery::Or(queries) => {
+                for (i, query) in queries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " OR ")?;
+                    }
+                    write!(f, "{}", query)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_db;
+    use libpobsd::GameDataBase;
+    fn get_names(db: &GameDataBase, query: &str) -> Vec<String> {
+        let query = GameQuery::parse(query).unwrap();
+        db.get_all_games()
+            .into_iter()
+            .filter(|game| query.matches(game))
+            .map(|game| game.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        let query =
+            GameQuery::parse("genre=RPG and runtime in (FNA, HashLink) status >= Completable")
+                .unwrap();
+        assert_eq!(
+            query.to_string(),
+            "genre=RPG AND runtime IN (FNA, HashLink) AND status>=completable"
+        );
+        let query =
+            GameQuery::parse("NOT (tag=multiplayer OR name=\"dead cells\") OR year<2000").unwrap();
+        assert_eq!(
+            query.to_string(),
+            "NOT (tag=multiplayer OR name=\"dead cells\") OR year<2000"
+        );
+        // the displayed query is parsed back to the same query
+        let query = GameQuery::parse("(engine=godot OR engine=fna) AND (dev=a AND dev=b)").unwrap();
+        assert_eq!(GameQuery::parse(&query.to_string()).unwrap(), query);
+        // quotes and backslashes are escaped within quotes
+        let query = GameQuery::parse(r#"name="the \"hat\"" OR name="a\\b" OR name="c\d""#).unwrap();
+        assert_eq!(
+            query.to_string(),
+            r#"name="the \"hat\"" OR name=a\b OR name=c\d"#
+        );
+        assert_eq!(GameQuery::parse(&query.to_string()).unwrap(), query);
+    }
+    #[test]
+    fn test_from_filter() {
+        let mut filter = GameFilter::default();
+        assert_eq!(GameQuery::from_filter(&filter), None);
+        filter.set_engine("godot");
+        filter.set_status(Status::Perfect);
+        let query = GameQuery::from_filter(&filter).unwrap();
+        assert_eq!(query.to_string(), "engine=godot OR status=perfect");
+        let term = GameQuery::parse("status=perfect").unwrap();
+        assert!(!query.requires(&term));
+        let query = GameQuery::parse("engine=godot AND status=perfect").unwrap();
+        assert!(query.requires(&term));
+    }
+    #[test]
+    fn test_parse_errors() {
+        assert!(GameQuery::parse("").is_err());
+        assert!(GameQuery::parse("genre=RPG AND").is_err());
+        assert!(GameQuery::parse("foo=bar").is_err());
+        assert!(GameQuery::parse("(genre=RPG").is_err());
+        assert!(GameQuery::parse("genre>RPG").is_err());
+        assert!(GameQuery::parse("status=working").is_err());
+        assert!(GameQuery::parse("name=\"unclosed").is_err());
+        assert!(GameQuery::parse("stardew valley").is_err());
+        // a deeply nested query is refused instead of overflowing the stack
+        let nested = format!("{}name=a{}", "(".repeat(5000), ")".repeat(5000));
+        assert!(GameQuery::parse(&nested).is_err());
+        assert!(GameQuery::parse(&format!("{}name=a", "NOT ".repeat(5000))).is_err());
+        let nested = format!("{}name=a{}", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH));
+        assert!(GameQuery::parse(&nested).is_ok());
+        assert!(GameQuery::is_query("genre=RPG AND"));
+        assert!(!GameQuery::is_query("stardew valley"));
+    }
+    #[test]
+    fn test_matches() {
+        let db = test_db();
+        assert_eq!(
+            get_names(&db, "engine=godot"),
+            vec!["The Adventures of Mr. Hat"]
+        );
+        let all = db.get_all_games().count;
+        let not_godot = get_names(&db, "NOT engine=godot");
+        assert_eq!(not_godot.len(), all - 1);
+        assert_eq!(get_names(&db, "engine!=godot"), not_godot);
+        let either = get_names(&db, "runtime IN (godot, HashLink)");
+        assert_eq!(either.len(), 2);
+        assert_eq!(get_names(&db, "runtime=godot OR runtime=hashlink"), either);
+        assert!(get_names(&db, "runtime=godot AND runtime=hashlink").is_empty());
+        // == only matches the whole value
+        assert_eq!(get_names(&db, "genre=platformer").len(), 4);
+        assert_eq!(get_names(&db, "genre==platformer").len(), 2);
+        assert_eq!(get_names(&db, "genre==\"puzzle PLATFORMER\"").len(), 2);
+        // statuses are ordered from doesnotrun to perfect
+        let at_least = get_names(&db, "status>=completable").len();
+        let below = get_names(&db, "status<completable").len();
+        let unknown = get_names(&db, "status=unknown").len();
+        assert!(unknown > 0);
+        assert_eq!(at_least + below + unknown, all);
+        assert_eq!(get_names(&db, "status<=unknown").len(), 0);
+    }
+}
@@ -2,50 +2,47 @@ use libpobsd::GameDataBase;
 use tokio::sync::oneshot::Sender as OsSender;
 
 use crate::{
-    db::{data::GameMetaData, responses::AppDbResponse},
-    utils::GameFilterWrapper,
+    app::config::{MetadataConfig, PaginationConfig},
+    db::responses::AppDbResponse,
+    provider::{FetchErrorKind, FetchedMetaData, MetadataKeys},
+    utils::DbState,
 };
 
+// Reads are served from the snapshot, only writes go through the AppDb
 pub enum AppDbRequest {
-    GetGame {
+    // sent by readers without waiting for an answer
+    FetchMetadata {
         game_id: u32,
-        ack: OsSender<AppDbResponse>,
-    },
-    GetGameIds {
-        ack: OsSender<AppDbResponse>,
-    },
-    GetAllGames {
-        ack: OsSender<AppDbResponse>,
-    },
-    GetGameStats {
-        ack: OsSender<AppDbResponse>,
+        high_priority: bool,
     },
-    GetGameList {
-        game_ids: Vec<u32>,
-        ack: OsSender<AppDbResponse>,
-    },
-    GetGameListPaginatedFiltered {
-        page: usize,
-        filter: GameFilterWrapper,
+    // the keys used for the lookup, to tell whether it is outdated
+    InsertMetadata {
+        game_id: u32,
+        keys: MetadataKeys,
+        metadata: FetchedMetaData,
         ack: OsSender<AppDbResponse>,
     },
-    GetGameRepresentation {
+    RecordFetchFailure {
         game_id: u32,
+        keys: MetadataKeys,
+        kind: FetchErrorKind,
         ack: OsSender<AppDbResponse>,
     },
-    GetRandomUid {
+    UpdateDb {
+        game_db: Box<GameDataBase>,
         ack: OsSender<AppDbResponse>,
     },
-    GetRecentGames {
+    UpdateDbState {
+        db_state: DbState,
         ack: OsSender<AppDbResponse>,
     },
-    InsertMetadata {
-        game_id: u32,
-        metadata: GameMetaData,
+    UpdateMetadataConfig {
+        metadata_config: MetadataConfig,
+        metadata_max_age: u64,
         ack: OsSender<AppDbResponse>,
     },
-    UpdateDb {
-        game_db: GameDataBase,
+    UpdatePaginationConfig {
+        pagination_config: PaginationConfig,
         ack: OsSender<AppDbResponse>,
     },
 }
@@ -1,15 +1,27 @@
 use libpobsd::Game;
 use std::collections::HashSet;
-use tokio::sync::oneshot::Receiver as OsReceiver;
 
 use crate::{
     app::{pagination::Page, representation::GameRepresentation},
-    db::{data::GameMetaData, stats::GameStats},
+    cache::MetaDataCache,
+    db::{
+        changelog::{ChangeLog, GameRef},
+        data::GameMetaData,
+        entity::Entity,
+        facets::Facet,
+        stats::GameStats,
+    },
+    utils::DbState,
 };
 
+// Responses are moved once through a oneshot channel,
+// boxing the large variants would not buy anything
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum AppDbResponse {
     Done,
+    ChangeLog(Vec<ChangeLog>),
+    DbState(DbState),
     Error,
     Game(Game),
     GameStats(GameStats),
@@ -18,10 +30,15 @@ pub enum AppDbResponse {
     GameList(Vec<Game>),
     GameListPaginated(Vec<Game>, Page),
     GameMetaData(GameMetaData),
+    MetaDataCache(MetaDataCache),
     GameRepresentation(GameRepresentation),
     NoMetaData,
     NoGame,
-    Pending(OsReceiver<GameMetaData>),
+    NoPage,
     RandomUid(u32),
     RecentGames(Vec<GameRepresentation>),
+    Suggestions(Vec<GameRef>),
+    Facets(Vec<Facet>),
+    Facet(Facet),
+    Entity(Entity),
 }
@@ -0,0 +1,292 @@
+use libpobsd::{Game, GameDataBase};
+use std::collections::HashMap;
+
+use crate::{
+    cache::MetaDataEntries,
+    db::{changelog::GameRef, data::GameMetaData},
+};
+
+// weight of a token depending on the field it comes from
+const NAME_WEIGHT: u32 = 10;
+const TAG_WEIGHT: u32 = 4;
+const CREDIT_WEIGHT: u32 = 4;
+const ENGINE_WEIGHT: u32 = 3;
+const YEAR_WEIGHT: u32 = 2;
+const SUMMARY_WEIGHT: u32 = 1;
+// between 0 and 1, names less similar to the query are not suggested
+const MIN_SIMILARITY: f64 = 0.7;
+const MAX_SUGGESTIONS: usize = 5;
+// words that short are left out of the comparison word by word
+// when the query has longer ones, "the" or "of" would match many names
+const SHORT_WORD_LENGTH: usize = 3;
+
+// Lowercase words without accents, punctuation is dropped
+pub fn tokenize(text: &str) -> Vec<String> {
+    slug::slugify(text)
+        .split('-')
+        .filter(|token| !token.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// Similarity between the query and a name, either as a whole
+// or word by word so a name can be longer than the query
+fn similarity(query_words: &[String], name: &str) -> f64 {
+    let name_words = tokenize(name);
+    if query_words.is_empty() || name_words.is_empty() {
+        return 0.0;
+    }
+    let whole =
+        strsim::normalized_damerau_levenshtein(&query_words.join(" "), &name_words.join(" "));
+    let long_words: Vec<&String> = query_words
+        .iter()
+        .filter(|word| word.chars().count() > SHORT_WORD_LENGTH)
+        .collect();
+    let query_words: Vec<&String> = if long_words.is_empty() {
+        query_words.iter().collect()
+    } else {
+        long_words
+    };
+    let by_word = query_words
+        .iter()
+        .map(|query_word| {
+            name_words
+                .iter()
+                .map(|name_word| strsim::normalized_damerau_levenshtein(query_word, name_word))
+                .fold(0.0, f64::max)
+        })
+        .sum::<f64>()
+        / query_words.len() as f64;
+    whole.max(by_word)
+}
+
+// The names closest to a query with typos, the most similar first
+pub fn suggest(games: &GameDataBase, query: &str) -> Vec<GameRef> {
+    let query_words = tokenize(query);
+    let mut suggestions: Vec<(f64, &Game)> = games
+        .get_all_games()
+        .into_iter()
+        .map(|game| (similarity(&query_words, &game.name), game))
+        .filter(|(score, _)| *score >= MIN_SIMILARITY)
+        .collect();
+    // the sort is stable so the games keep the order by name
+    suggestions.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    suggestions
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, game)| GameRef::from(game))
+        .collect()
+}
+
+// Inverted index of the games, a token found in several fields
+// of a game or several times in a field weighs more. The maps are
+// persistent so indexing the metadata of a game in a copy of the
+// index does not copy all of it.
+#[derive(Clone, Default)]
+pub struct SearchIndex {
+    // token -> game_id -> weight of the token in the game
+    tokens: im::OrdMap<String, im::HashMap<u32, u32>>,
+    // weights added by the metadata of each game,
+    // removed when new metadata are indexed
+    metadata_tokens: im::HashMap<u32, HashMap<String, u32>>,
+}
+
+impl SearchIndex {
+    pub fn new(games: &GameDataBase, game_metadata: &MetaDataEntries) -> Self {
+        let mut index = Self::default();
+        for game in games.get_all_games() {
+            index.index_game(game);
+            if let Some(cached) = game_metadata.get(&game.uid) {
+                index.index_metadata(game.uid, &cached.metadata);
+            }
+        }
+        index
+    }
+    fn add(&mut self, game_id: u32, tokens: &HashMap<String, u32>) {
+        for (token, weight) in tokens {
+            *self
+                .tokens
+                .entry(token.clone())
+                .or_default()
+                .entry(game_id)
+                .or_default() += weight;
+        }
+    }
+    fn index_game(&mut self, game: &Game) {
+        let mut tokens: HashMap<String, u32> = HashMap::new();
+        let mut add_text = |text: &str, weight: u32| {
+            for token in tokenize(text) {
+                *tokens.entry(token).or_default() += weight;
+            }
+        };
+        add_text(&game.name, NAME_WEIGHT);
+        // the runtime weighs as much as the engine
+        for (item, weight) in [
+            (&game.engine, ENGINE_WEIGHT),
+            (&game.runtime, ENGINE_WEIGHT),
+            (&game.year, YEAR_WEIGHT),
+        ] {
+            if let Some(item) = item {
+                add_text(item, weight);
+            }
+        }
+        for (items, weight) in [
+            (&game.devs, CREDIT_WEIGHT),
+            (&game.publis, CREDIT_WEIGHT),
+            (&game.genres, TAG_WEIGHT),
+            (&game.tags, TAG_WEIGHT),
+        ] {
+            for item in items.iter().flatten() {
+                add_text(item, weight);
+            }
+        }
+        self.add(game.uid, &tokens);
+    }
+    pub fn index_metadata(&mut self, game_id: u32, metadata: &GameMetaData) {
+        if let Some(old_tokens) = self.metadata_tokens.remove(&game_id) {
+            for (token, weight) in old_tokens {
+                if let Some(postings) = self.tokens.get_mut(&token) {
+                    if let Some(game_weight) = postings.get_mut(&game_id) {
+                        *game_weight = game_weight.saturating_sub(weight);
+                        if *game_weight == 0 {
+                            postings.remove(&game_id);
+                        }
+                    }
+                    if postings.is_empty() {
+                        self.tokens.remove(&token);
+                    }
+                }
+            }
+        }
+        let mut tokens: HashMap<String, u32> = HashMap::new();
+        for text in [&metadata.summary, &metadata.story_line]
+            .into_iter()
+            .flatten()
+        {
+            for token in tokenize(text) {
+                *tokens.entry(token).or_default() += SUMMARY_WEIGHT;
+            }
+        }
+        self.add(game_id, &tokens);
+        self.metadata_tokens.insert(game_id, tokens);
+    }
+    // Every word of the query has to match a token of the game, either
+    // fully or as a prefix. A full match counts twice as much.
+    // Returns the score of each matching game.
+    pub fn search(&self, query: &str) -> HashMap<u32, u32> {
+        let mut scores: Option<HashMap<u32, u32>> = None;
+        for word in tokenize(query) {
+            let mut word_scores: HashMap<u32, u32> = HashMap::new();
+            for (token, postings) in self
+                .tokens
+                .range(word.clone()..)
+                .take_while(|(token, _)| token.starts_with(&word))
+            {
+                let factor = if token.eq(&word) { 2 } else { 1 };
+                for (game_id, weight) in postings {
+                    let score = word_scores.entry(*game_id).or_default();
+                    *score = (*score).max(weight * factor);
+                }
+            }
+            scores = Some(match scores {
+                None => word_scores,
+                Some(scores) => scores
+                    .into_iter()
+                    .filter_map(|(game_id, score)| {
+                        word_scores
+                            .get(&game_id)
+                            .map(|word_score| (game_id, score + word_score))
+                    })
+                    .collect(),
+            });
+        }
+        scores.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{game_id, test_db};
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("The Adventures of Mr. Hat"),
+            vec!["the", "adventures", "of", "mr", "hat"]
+        );
+        assert_eq!(tokenize("Pokémon: 2!"), vec!["pokemon", "2"]);
+        assert!(tokenize("...").is_empty());
+    }
+    #[test]
+    fn test_search_by_prefix_and_ranking() {
+        let db = test_db();
+        let index = SearchIndex::new(&db, &MetaDataEntries::new());
+        let game_id = game_id(&db, "The Adventures of Mr. Hat");
+        let results = index.search("adventures hat");
+        assert_eq!(results.len(), 1);
+        assert!(results.contains_key(&game_id));
+        // a prefix match
+        assert!(index.search("adv H").contains_key(&game_id));
+        // every word has to match
+        assert!(index.search("adventures nothingmatches").is_empty());
+        // a full match weighs more than a prefix match
+        let full = index.search("godot")[&game_id];
+        let prefix = index.search("god")[&game_id];
+        assert!(full > prefix);
+    }
+    #[test]
+    fn test_search_runtime_and_year() {
+        let db = test_db();
+        let index = SearchIndex::new(&db, &MetaDataEntries::new());
+        let game_id = game_id(&db, "Aeternum");
+        assert!(index.search("fnaify").contains_key(&game_id));
+        assert!(index.search("2017").contains_key(&game_id));
+    }
+    #[test]
+    fn test_suggest() {
+        let db = test_db();
+        let suggestions = suggest(&db, "adventures of mr hta");
+        assert_eq!(suggestions[0].name, "The Adventures of Mr. Hat");
+        let suggestions = suggest(&db, "aeternun");
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].name, "Aeternum");
+        assert!(suggest(&db, "zzzzzzzz").is_empty());
+        // common short words do not bring the other games starting with them
+        let suggestions = suggest(&db, "the mr shugy");
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].name, "The Adventures of Shuggy");
+    }
+    #[test]
+    fn test_search_summary() {
+        let db = test_db();
+        let game_id = game_id(&db, "The Adventures of Mr. Hat");
+        let mut index = SearchIndex::new(&db, &MetaDataEntries::new());
+        assert!(index.search("umbrella").is_empty());
+        let metadata = GameMetaData {
+            summary: Some("A hat with an umbrella".into()),
+            ..Default::default()
+        };
+        // indexing a copy leaves the original untouched
+        let original = index.clone();
+        index.index_metadata(game_id, &metadata);
+        assert_eq!(index.search("umbrella").len(), 1);
+        assert!(original.search("umbrella").is_empty());
+        // new metadata replace the old ones
+        let metadata = GameMetaData {
+            summary: Some("A hat without anything".into()),
+            ..Default::default()
+        };
+        index.index_metadata(game_id, &metadata);
+        assert!(index.search("umbrella").is_empty());
+        // the name is still indexed
+        assert!(index.search("hat").contains_key(&game_id));
+        // the story line is indexed too
+        let metadata = GameMetaData {
+            story_line: Some("A hat lost in the rain".into()),
+            ..Default::default()
+        };
+        index.index_metadata(game_id, &metadata);
+        assert!(index.search("rain").contains_key(&game_id));
+    }
+}
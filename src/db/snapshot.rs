@@ -0,0 +1,429 @@
+use chrono::{Duration, Local};
+use libpobsd::{Game, GameDataBase};
+use rand::prelude::*;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+};
+
+use crate::{
+    app::{
+        config::{MetadataConfig, PaginationConfig, ProviderKind},
+        pagination::Paginator,
+        representation::GameRepresentation,
+    },
+    cache::{CachedMetaData, FailedLookups, MetaDataCache, MetaDataEntries},
+    db::{
+        changelog::ChangeLog,
+        entity::Entity,
+        facets::FacetIndex,
+        query::{Field, GameQuery},
+        responses::AppDbResponse,
+        search::{suggest, SearchIndex},
+        sort::Sort,
+        stats::GameStats,
+    },
+    utils::DbState,
+};
+
+// The games listed, all of them when nothing is set
+#[derive(Debug, Clone, Default)]
+pub struct GameSelection {
+    pub search: Option<String>,
+    pub query: Option<GameQuery>,
+    pub sort: Option<Sort>,
+}
+
+// Everything derived from one version of the game database
+#[derive(Clone, Default)]
+pub struct GameData {
+    pub stats: GameStats,
+    pub facets: FacetIndex,
+    pub last_updated: String,
+    pub games: GameDataBase,
+    pub game_ids: HashSet<u32>,
+}
+
+impl GameData {
+    pub fn new(games: GameDataBase) -> Self {
+        let game_ids: HashSet<u32> = games.get_all_games().into_iter().map(|g| g.uid).collect();
+        let facets = FacetIndex::new(&games);
+        let mut game_data = Self {
+            facets,
+            games,
+            game_ids,
+            last_updated: chrono::Utc::now().to_rfc2822(),
+            stats: GameStats::default(),
+        };
+        game_data.update_stats();
+        game_data
+    }
+}
+
+// Immutable view of the AppDb served to the readers. Each part is behind
+// its own Arc or is a persistent map so a write only copies what it changes.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub game_data: Arc<GameData>,
+    // covers the games and their metadata
+    pub search_index: Arc<SearchIndex>,
+    pub game_metadata: MetaDataEntries,
+    pub failed_lookups: FailedLookups,
+    pub metadata_config: Arc<MetadataConfig>,
+    // in seconds
+    pub metadata_max_age: u64,
+    pub pagination_config: Arc<PaginationConfig>,
+    // most recent update first
+    pub changelog: Arc<VecDeque<ChangeLog>>,
+    pub db_state: Arc<DbState>,
+}
+
+impl Snapshot {
+    // Only the providers that are enabled are taken into account
+    // so pages do not wait for a provider that will never answer
+    pub fn game_has_metadata(&self, game_id: u32) -> bool {
+        match self.game_data.games.get_game_by_id(game_id) {
+            Some(game) => {
+                (game.igdb_id.is_some() && self.metadata_config.is_enabled(ProviderKind::Igdb))
+                    || (game.get_steam_id().is_some()
+                        && self.metadata_config.is_enabled(ProviderKind::Steam))
+            }
+            None => false,
+        }
+    }
+    // A game whose last lookup failed is not fetched
+    // again before the end of its retry delay
+    pub fn is_backing_off(&self, game_id: u32) -> bool {
+        match self.failed_lookups.get(&game_id) {
+            Some(failed_lookup) => !failed_lookup.can_retry(),
+            None => false,
+        }
+    }
+    // Some(true) for missing metadata that a page is waiting for,
+    // Some(false) for stale metadata refreshed in the background
+    pub fn metadata_to_fetch(&self, game_id: u32) -> Option<bool> {
+        if !self.game_has_metadata(game_id) || self.is_backing_off(game_id) {
+            return None;
+        }
+        match self.game_metadata.get(&game_id) {
+            Some(cached) if cached.is_fresh(&self.metadata_config, self.metadata_max_age) => None,
+            Some(_) => Some(false),
+            None => Some(true),
+        }
+    }
+    pub fn get_game_by_id(&self, game_id: u32) -> AppDbResponse {
+        match self.game_data.games.get_game_by_id(game_id) {
+            Some(game) => AppDbResponse::Game(game.clone()),
+            None => AppDbResponse::NoGame,
+        }
+    }
+    pub fn get_changelog(&self) -> AppDbResponse {
+        AppDbResponse::ChangeLog(self.changelog.iter().cloned().collect())
+    }
+    pub fn get_db_state(&self) -> AppDbResponse {
+        AppDbResponse::DbState(self.db_state.as_ref().clone())
+    }
+    pub fn get_game_ids(&self) -> AppDbResponse {
+        AppDbResponse::GameIds(self.game_data.game_ids.clone())
+    }
+    pub fn get_game_stats(&self) -> AppDbResponse {
+        AppDbResponse::GameStats(self.game_data.stats.clone())
+    }
+    pub fn get_all_games(&self) -> AppDbResponse {
+        let games: Vec<Game> = self
+            .game_data
+            .games
+            .get_all_games()
+            .into_iter()
+            .cloned()
+            .collect();
+        AppDbResponse::AllGames(games)
+    }
+    // Stale metadata are returned as they are, a game without metadata
+    // yet is returned with metadata_pending set while they are fetched
+    pub fn get_game_representation_by_id(&self, game_id: u32) -> AppDbResponse {
+        match self.game_data.games.get_game_by_id(game_id) {
+            Some(game) => {
+                let game = game.clone();
+                if !self.game_has_metadata(game_id) {
+                    return AppDbResponse::GameRepresentation(GameRepresentation::new(game, None));
+                }
+                match self.game_metadata.get(&game_id) {
+                    Some(cached) => AppDbResponse::GameRepresentation(GameRepresentation::new(
+                        game,
+                        Some(cached.metadata.clone()),
+                    )),
+                    // a game known to fail is not pending
+                    None => AppDbResponse::GameRepresentation(GameRepresentation {
+                        game,
+                        metadata: None,
+                        metadata_pending: !self.is_backing_off(game_id),
+                    }),
+                }
+            }
+            None => AppDbResponse::NoGame,
+        }
+    }
+    pub fn get_game_list(&self, game_ids: Vec<u32>) -> AppDbResponse {
+        let mut games: Vec<Game> = vec![];
+        for game_id in game_ids {
+            if let Some(game) = self.game_data.games.get_game_by_id(game_id) {
+                games.push(game.clone());
+            }
+        }
+        AppDbResponse::GameList(games)
+    }
+    // Games matching the full text search, ordered by relevance,
+    // or all the games, then kept if they match the query
+    fn select_games(&self, selection: &GameSelection) -> Vec<&Game> {
+        let mut games: Vec<&Game> = self.game_data.games.get_all_games().into_inner();
+        if let Some(ref search) = selection.search {
+            let scores = self.search_index.search(search);
+            games.retain(|game| scores.contains_key(&game.uid));
+            // the sort is stable so the games keep the order by name
+            games.sort_by(|a, b| scores[&b.uid].cmp(&scores[&a.uid]));
+        }
+        if let Some(ref query) = selection.query {
+            games.retain(|game| query.matches(game));
+        }
+        games
+    }
+    // Games are sorted before the page is sliced
+    pub fn get_game_list_paginated(
+        &self,
+        selection: &GameSelection,
+        page: usize,
+        per_page: Option<usize>,
+    ) -> AppDbResponse {
+        let mut games: Vec<Game> = self.select_games(selection).into_iter().cloned().collect();
+        if let Some(sort) = selection.sort {
+            sort.sort_games(&mut games, &self.game_metadata);
+        }
+        paginate(games, page, self.pagination_config.per_page(per_page))
+    }
+    pub fn get_facets(&self, selection: &GameSelection) -> AppDbResponse {
+        let game_ids: HashSet<u32> = self
+            .select_games(selection)
+            .into_iter()
+            .map(|game| game.uid)
+            .collect();
+        AppDbResponse::Facets(self.game_data.facets.facets(&game_ids))
+    }
+    pub fn get_facet(&self, field: Field) -> AppDbResponse {
+        match self.game_data.facets.facet(field) {
+            Some(facet) => AppDbResponse::Facet(facet),
+            None => AppDbResponse::Error,
+        }
+    }
+    pub fn get_entity(&self, field: Field, name: &str) -> AppDbResponse {
+        match Entity::new(&self.game_data.games, &self.game_data.facets, field, name) {
+            Some(entity) => AppDbResponse::Entity(entity),
+            None => AppDbResponse::NoGame,
+        }
+    }
+    pub fn get_suggestions(&self, query: &str) -> AppDbResponse {
+        AppDbResponse::Suggestions(suggest(&self.game_data.games, query))
+    }
+    pub fn get_random_uid(&self) -> AppDbResponse {
+        let games = self.game_data.games.get_all_games();
+        let game_number = games.count;
+        let rid = thread_rng().gen_range(0..game_number);
+        AppDbResponse::RandomUid(games.into_inner()[rid].uid)
+    }
+    pub fn get_recent_games(&self) -> AppDbResponse {
+        let now = Local::now().naive_local().date();
+        let mut games: Vec<&Game> = self
+            .game_data
+            .games
+            .get_all_games()
+            .into_iter()
+            .filter(|a| now - a.added < Duration::try_days(30).unwrap())
+            .collect();
+        games.sort_by(|a, b| a.added.partial_cmp(&b.added).unwrap());
+        games.reverse();
+        let mut grs: Vec<GameRepresentation> = vec![];
+        for game in games {
+            grs.push(GameRepresentation::new(
+                game.clone(),
+                self.game_metadata
+                    .get(&game.uid)
+                    .map(|cached| cached.metadata.clone()),
+            ))
+        }
+        AppDbResponse::RecentGames(grs)
+    }
+    pub fn get_metadata_cache(&self) -> AppDbResponse {
+        let entries: HashMap<u32, CachedMetaData> = self
+            .game_metadata
+            .iter()
+            .map(|(game_id, cached)| (*game_id, cached.as_ref().clone()))
+            .collect();
+        let mut metadata_cache = MetaDataCache::new(entries);
+        metadata_cache.failures = self
+            .failed_lookups
+            .iter()
+            .map(|(game_id, failed_lookup)| (*game_id, failed_lookup.clone()))
+            .collect();
+        AppDbResponse::MetaDataCache(metadata_cache)
+    }
+    // Games waiting for a retry after a failed lookup are
+    // returned too since they do not need to be fetched
+    pub fn get_fresh_metadata_ids(&self) -> AppDbResponse {
+        let mut game_ids: HashSet<u32> = self
+            .game_metadata
+            .iter()
+            .filter(|(_, cached)| cached.is_fresh(&self.metadata_config, self.metadata_max_age))
+            .map(|(game_id, _)| *game_id)
+            .collect();
+        game_ids.extend(
+            self.failed_lookups
+                .keys()
+                .filter(|game_id| self.is_backing_off(**game_id)),
+        );
+        AppDbResponse::GameIds(game_ids)
+    }
+}
+
+fn paginate(games: Vec<Game>, page: usize, per_page: usize) -> AppDbResponse {
+    match Paginator::new(games.len(), per_page).page(page) {
+        Some(page) => AppDbResponse::GameListPaginated(
+            games[page.first_element..=page.last_element].to_vec(),
+            page,
+        ),
+        // It can be None because the requested page does not exist
+        // or the list of games is empty and the Paginator returns None
+        None => {
+            if games.is_empty() {
+                AppDbResponse::NoGame
+            } else {
+                AppDbResponse::NoPage
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cache::FailedLookup,
+        db::data::GameMetaData,
+        provider::FetchErrorKind,
+        test_utils::{game_id, test_db},
+    };
+
+    fn get_snapshot() -> Snapshot {
+        let games = test_db();
+        Snapshot {
+            search_index: Arc::new(SearchIndex::new(&games, &MetaDataEntries::new())),
+            game_data: Arc::new(GameData::new(games)),
+            game_metadata: MetaDataEntries::new(),
+            failed_lookups: FailedLookups::new(),
+            metadata_config: Arc::new(MetadataConfig::default()),
+            metadata_max_age: 3600,
+            pagination_config: Arc::new(PaginationConfig::default()),
+            changelog: Arc::new(VecDeque::new()),
+            db_state: Arc::new(DbState::default()),
+        }
+    }
+
+    #[test]
+    fn test_missing_metadata_are_pending() {
+        let snapshot = get_snapshot();
+        let game_id = game_id(&snapshot.game_data.games, "The Adventures of Mr. Hat");
+        assert_eq!(snapshot.metadata_to_fetch(game_id), Some(true));
+        match snapshot.get_game_representation_by_id(game_id) {
+            AppDbResponse::GameRepresentation(gr) => assert!(gr.metadata_pending),
+            _ => panic!("Expected a GameRepresentation"),
+        }
+    }
+    #[test]
+    fn test_failed_lookup_is_not_pending() {
+        let mut snapshot = get_snapshot();
+        let game_id = game_id(&snapshot.game_data.games, "The Adventures of Mr. Hat");
+        snapshot.failed_lookups =
+            FailedLookups::unit(game_id, FailedLookup::new(FetchErrorKind::NotFound));
+        assert_eq!(snapshot.metadata_to_fetch(game_id), None);
+        match snapshot.get_game_representation_by_id(game_id) {
+            AppDbResponse::GameRepresentation(gr) => assert!(!gr.metadata_pending),
+            _ => panic!("Expected a GameRepresentation"),
+        }
+    }
+    #[test]
+    fn test_stale_metadata_are_refreshed() {
+        let mut snapshot = get_snapshot();
+        let game_id = game_id(&snapshot.game_data.games, "The Adventures of Mr. Hat");
+        let mut cached = CachedMetaData::new(GameMetaData::default());
+        snapshot.game_metadata = MetaDataEntries::unit(game_id, Arc::new(cached.clone()));
+        assert_eq!(snapshot.metadata_to_fetch(game_id), None);
+        cached.fetched_at = chrono::Utc::now() - Duration::try_seconds(7200).unwrap();
+        snapshot.game_metadata = MetaDataEntries::unit(game_id, Arc::new(cached));
+        assert_eq!(snapshot.metadata_to_fetch(game_id), Some(false));
+    }
+    fn search(search: &str) -> GameSelection {
+        GameSelection {
+            search: Some(search.into()),
+            ..Default::default()
+        }
+    }
+    fn query(query: &str) -> GameSelection {
+        GameSelection {
+            query: Some(GameQuery::parse(query).unwrap()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_search_is_ranked() {
+        let snapshot = get_snapshot();
+        match snapshot.get_game_list_paginated(&search("godot"), 1, None) {
+            AppDbResponse::GameListPaginated(games, _) => {
+                // games made with godot are listed before the other matches
+                let engines: Vec<bool> = games
+                    .iter()
+                    .map(|game| game.engine.as_deref() == Some("godot"))
+                    .collect();
+                assert!(engines[0]);
+                assert!(engines.windows(2).all(|w| w[0] || !w[1]));
+            }
+            _ => panic!("Expected a GameListPaginated"),
+        }
+        assert!(matches!(
+            snapshot.get_game_list_paginated(&search("nothingmatches"), 1, None),
+            AppDbResponse::NoGame
+        ));
+    }
+    #[test]
+    fn test_query_game_list() {
+        let snapshot = get_snapshot();
+        let selection = query("engine=godot OR runtime=HashLink");
+        match snapshot.get_game_list_paginated(&selection, 1, None) {
+            AppDbResponse::GameListPaginated(games, _) => assert_eq!(games.len(), 2),
+            _ => panic!("Expected a GameListPaginated"),
+        }
+        let selection = query("engine=godot AND NOT name=hat");
+        assert!(matches!(
+            snapshot.get_game_list_paginated(&selection, 1, None),
+            AppDbResponse::NoGame
+        ));
+        // the search and the query are combined
+        let mut selection = query("genre==RPG");
+        selection.search = Some("aedemphia".into());
+        match snapshot.get_game_list_paginated(&selection, 1, None) {
+            AppDbResponse::GameListPaginated(games, _) => assert_eq!(games.len(), 1),
+            _ => panic!("Expected a GameListPaginated"),
+        }
+    }
+    #[test]
+    fn test_facets_follow_the_selection() {
+        let snapshot = get_snapshot();
+        match snapshot.get_facets(&query("genre==RPG")) {
+            AppDbResponse::Facets(facets) => {
+                let genre = facets.iter().find(|facet| facet.label == "Genre").unwrap();
+                assert_eq!(genre.values.len(), 1);
+                assert_eq!(genre.values[0].count, 2);
+            }
+            _ => panic!("Expected Facets"),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use libpobsd::Game;
+use serde::Deserialize;
+use std::cmp::Ordering;
+
+use crate::cache::MetaDataEntries;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Name,
+    Year,
+    Added,
+    Updated,
+    Status,
+    Metacritic,
+}
+
+impl SortKey {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Year => "year",
+            SortKey::Added => "added",
+            SortKey::Updated => "updated",
+            SortKey::Status => "status",
+            SortKey::Metacritic => "metacritic",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sort {
+    pub key: SortKey,
+    pub order: SortOrder,
+}
+
+// Games without a value are listed last whatever the order
+fn sort_by<K: Ord>(games: &mut [Game], order: SortOrder, key: impl Fn(&Game) -> Option<K>) {
+    games.sort_by(|a, b| match (key(a), key(b)) {
+        (Some(a), Some(b)) => match order {
+            SortOrder::Asc => a.cmp(&b),
+            SortOrder::Desc => b.cmp(&a),
+        },
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+}
+
+impl Sort {
+    pub fn query_string(&self) -> String {
+        format!("sort={}&order={}", self.key.as_str(), self.order.as_str())
+    }
+    // The sort is stable, games with the same value
+    // keep the order they are given in
+    pub fn sort_games(&self, games: &mut [Game], game_metadata: &MetaDataEntries) {
+        match self.key {
+            SortKey::Name => sort_by(games, self.order, |game| Some(game.name.to_lowercase())),
+            // the year can be text such as "early access"
+            SortKey::Year => sort_by(games, self.order, |game| {
+                game.year
+                    .as_deref()
+                    .and_then(|year| year.parse::<u32>().ok())
+            }),
+            SortKey::Added => sort_by(games, self.order, |game| Some(game.added)),
+            SortKey::Updated => sort_by(games, self.order, |game| Some(game.updated)),
+            SortKey::Status => sort_by(games, self.order, |game| Some(game.status.status.clone())),
+            SortKey::Metacritic => sort_by(games, self.order, |game| {
+                game_metadata
+                    .get(&game.uid)
+                    .and_then(|cached| cached.metadata.metacritic.as_ref())
+                    .map(|metacritic| metacritic.score)
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cache::CachedMetaData,
+        db::data::{GameMetaData, Metacritic},
+        test_utils::test_games,
+    };
+    use std::sync::Arc;
+
+    fn get_years(games: &[Game]) -> Vec<Option<String>> {
+        games.iter().map(|game| game.year.clone()).collect()
+    }
+
+    #[test]
+    fn test_sort_by_year() {
+        let mut games = test_games();
+        let sort = Sort {
+            key: SortKey::Year,
+            order: SortOrder::Desc,
+        };
+        sort.sort_games(&mut games, &MetaDataEntries::new());
+        let years = get_years(&games);
+        assert_eq!(years[0].as_deref(), Some("2022"));
+        // games without a year come last
+        assert_eq!(years.last().unwrap(), &None);
+        let sort = Sort {
+            key: SortKey::Year,
+            order: SortOrder::Asc,
+        };
+        sort.sort_games(&mut games, &MetaDataEntries::new());
+        let years = get_years(&games);
+        assert_eq!(years[0].as_deref(), Some("2011"));
+        assert_eq!(years.last().unwrap(), &None);
+    }
+    #[test]
+    fn test_sort_by_metacritic() {
+        let mut games = test_games();
+        let game_id = games[3].uid;
+        let metadata = GameMetaData {
+            metacritic: Some(Metacritic {
+                score: 80,
+                url: "https://www.metacritic.com".into(),
+            }),
+            ..Default::default()
+        };
+        let game_metadata = MetaDataEntries::unit(game_id, Arc::new(CachedMetaData::new(metadata)));
+        let sort = Sort {
+            key: SortKey::Metacritic,
+            order: SortOrder::Desc,
+        };
+        sort.sort_games(&mut games, &game_metadata);
+        assert_eq!(games[0].uid, game_id);
+        assert_eq!(sort.query_string(), "sort=metacritic&order=desc");
+    }
+}
@@ -1,8 +1,9 @@
 use libpobsd::{GameFilter, SearchType, Status};
+use serde::Serialize;
 
-use super::AppDb;
+use super::snapshot::GameData;
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct GameStats {
     pub engine_stats: Vec<(String, usize, String)>,
     pub runtime_stats: Vec<(String, usize, String)>,
@@ -15,7 +16,7 @@ pub struct GameStats {
     pub total_games: usize,
 }
 
-impl AppDb {
+impl GameData {
     pub fn update_stats(&mut self) {
         let st = SearchType::NotCaseSensitive;
 
@@ -68,7 +69,7 @@ impl AppDb {
             .iter()
             .map(|x| (x.0.clone(), x.1.len(), x.0.clone()))
             .collect();
-        engine_stats.sort_by(|a, b| a.1.cmp(&b.1));
+        engine_stats.sort_by_key(|a| a.1);
         engine_stats.reverse();
         engine_stats.truncate(15);
 
@@ -78,7 +79,7 @@ impl AppDb {
             .iter()
             .map(|x| (x.0.clone(), x.1.len(), x.0.clone()))
             .collect();
-        runtime_stats.sort_by(|a, b| a.1.cmp(&b.1));
+        runtime_stats.sort_by_key(|a| a.1);
         runtime_stats.reverse();
         runtime_stats.truncate(15);
 
@@ -88,7 +89,7 @@ impl AppDb {
             .iter()
             .map(|x| (x.0.clone(), x.1.len(), x.0.clone()))
             .collect();
-        genre_stats.sort_by(|a, b| a.1.cmp(&b.1));
+        genre_stats.sort_by_key(|a| a.1);
         genre_stats.reverse();
         genre_stats.truncate(15);
 
@@ -98,7 +99,7 @@ impl AppDb {
             .iter()
             .map(|x| (x.0.clone(), x.1.len(), x.0.clone()))
             .collect();
-        tag_stats.sort_by(|a, b| a.1.cmp(&b.1));
+        tag_stats.sort_by_key(|a| a.1);
         tag_stats.reverse();
         tag_stats.truncate(15);
 
@@ -108,7 +109,7 @@ impl AppDb {
             .iter()
             .map(|x| (x.0.clone(), x.1.len(), x.0.clone()))
             .collect();
-        year_stats.sort_by(|a, b| a.1.cmp(&b.1));
+        year_stats.sort_by_key(|a| a.1);
         year_stats.reverse();
         year_stats.truncate(15);
         year_stats.sort_by(|a, b| a.0.cmp(&b.0));
@@ -120,7 +121,7 @@ impl AppDb {
             .iter()
             .map(|x| (x.0.clone(), x.1.len(), x.0.clone()))
             .collect();
-        dev_stats.sort_by(|a, b| a.1.cmp(&b.1));
+        dev_stats.sort_by_key(|a| a.1);
         dev_stats.reverse();
         dev_stats.truncate(15);
 
@@ -130,7 +131,7 @@ impl AppDb {
             .iter()
             .map(|x| (x.0.clone(), x.1.len(), x.0.clone()))
             .collect();
-        publi_stats.sort_by(|a, b| a.1.cmp(&b.1));
+        publi_stats.sort_by_key(|a| a.1);
         publi_stats.reverse();
         publi_stats.truncate(15);
 
@@ -1,24 +1,74 @@
+use arc_swap::ArcSwap;
+use std::sync::Arc;
 use tokio::{
     sync::{
-        mpsc::{self, Sender as MspcSender},
+        mpsc::{self, Receiver as MspcReceiver, Sender as MspcSender},
         oneshot::Sender as OneShotSender,
+        watch::Receiver as WatchReceiver,
+        Semaphore,
     },
-    task::JoinHandle,
+    task::{JoinHandle, JoinSet},
 };
 
 use crate::{
-    db::data::GameMetaData,
-    igdb::client::{IgdbClient, IgdbMetaData},
-    metacritic::MetacriticClient,
-    steam::{SteamClient, SteamMetaData},
+    limiter::RateLimiter,
+    provider::{
+        FetchErrorKind, FetchResult, FetchedMetaData, MetadataKeys, MetadataProvider,
+        ProviderAnswer,
+    },
 };
 
+// in seconds, a provider taking longer is considered unreachable
+const PROVIDER_TIMEOUT: u64 = 10;
+// number of messages of a same priority processed at the same time
+const MAX_CONCURRENT_FETCHES: usize = 8;
+
 #[derive(Debug)]
 pub struct FetcherMessage {
-    pub igdb_id: Option<usize>,
-    pub steam_id: Option<usize>,
-    pub game_name: String,
-    pub sender: OneShotSender<GameMetaData>,
+    pub keys: MetadataKeys,
+    pub sender: OneShotSender<FetchResult>,
+}
+
+// A provider along with the rate limiter shared
+// by every fetch sent to it
+struct LimitedProvider {
+    provider: Arc<dyn MetadataProvider>,
+    limiter: RateLimiter,
+}
+
+// The providers used by the fetcher, they can be replaced while
+// it runs, the fetches in progress keep the previous ones
+#[derive(Clone)]
+pub struct ProviderSet(Arc<ArcSwap<Vec<LimitedProvider>>>);
+
+impl ProviderSet {
+    fn new(providers: Vec<Arc<dyn MetadataProvider>>) -> Self {
+        Self(Arc::new(ArcSwap::from_pointee(limit_providers(providers))))
+    }
+    pub fn replace(&self, providers: Vec<Arc<dyn MetadataProvider>>) {
+        self.0.store(Arc::new(limit_providers(providers)));
+    }
+    fn load(&self) -> Arc<Vec<LimitedProvider>> {
+        self.0.load_full()
+    }
+}
+
+fn limit_providers(providers: Vec<Arc<dyn MetadataProvider>>) -> Vec<LimitedProvider> {
+    tracing::info!(
+        "Enabled metadata providers: {}",
+        providers
+            .iter()
+            .map(|provider| provider.name())
+            .collect::<Vec<&str>>()
+            .join(", ")
+    );
+    providers
+        .into_iter()
+        .map(|provider| LimitedProvider {
+            limiter: RateLimiter::new(provider.rate_limit()),
+            provider,
+        })
+        .collect()
 }
 
 pub struct MetaDataFetcher {
@@ -26,79 +76,253 @@ pub struct MetaDataFetcher {
     pub low_priority: MspcSender<FetcherMessage>,
     // used to receive request related fetching
     pub high_priority: MspcSender<FetcherMessage>,
+    pub providers: ProviderSet,
     pub join_handler: tokio::task::JoinHandle<anyhow::Result<()>>,
 }
 
 impl MetaDataFetcher {
-    pub async fn init(client_id: String, client_secret: String) -> Self {
+    // providers are queried in the given order and their
+    // metadata merged following that order of priority
+    pub fn init(providers: Vec<Arc<dyn MetadataProvider>>, shutdown: WatchReceiver<bool>) -> Self {
         // used to prefetch medatada and put them in cache
         // it is low priority vs metadata used in response
         // to requests
-        let (lptx, mut lprx) = mpsc::channel(300);
+        let (lptx, lprx) = mpsc::channel(300);
         // used to provide metadata in response to requests
         // is high priority (results are cached)
-        let (hptx, mut hprx) = mpsc::channel(300);
-        // obtain the Client before spawning the thread so we are sure the client is
-        // ready when the metadata requests arrive. Need to be mutable to be able to
-        // refresh the token when fetching.
-        let mut igdb_client = IgdbClient::new(client_id.clone(), client_secret.clone())
-            .await
-            .expect("Fail to obtain a Igdb client. Aborting");
-        let steam_client = SteamClient::new();
-        let metacritic_client = MetacriticClient::new();
+        let (hptx, hprx) = mpsc::channel(300);
+        let providers = ProviderSet::new(providers);
+        // each priority has its own slots and the rate limiters serve the
+        // requests first so background fetches do not delay them
         let join_handler: JoinHandle<anyhow::Result<()>> = tokio::spawn({
+            let providers = providers.clone();
             async move {
-                loop {
-                    let fetcher_message: FetcherMessage = tokio::select! {
-                        // using biased select, hprx takes precedente over lprx
-                        biased;
-                        Some(h) = hprx.recv() => {
-                            h
-                        }
-                        Some(l) = lprx.recv() => {
-                            l
-                        }
-                    };
-                    let igdb_metadata = match fetcher_message.igdb_id {
-                        Some(igdb_id) => match igdb_client.fetch_metadata(igdb_id).await {
-                            Ok(game_metadata) => game_metadata,
-                            Err(e) => {
-                                tracing::debug!("Error while fetching igdb metadata: {e}");
-                                IgdbMetaData::default()
-                            }
-                        },
-                        None => IgdbMetaData::default(),
-                    };
-                    let steam_metadata = match fetcher_message.steam_id {
-                        Some(steam_id) => match steam_client.fetch_metadata(steam_id).await {
-                            Ok(game_metadata) => game_metadata,
-                            Err(e) => {
-                                tracing::debug!("Error while fetching steam metadata: {e}");
-                                SteamMetaData::default()
-                            }
-                        },
-                        None => SteamMetaData::default(),
-                    };
-                    let metacritic_metadata = metacritic_client
-                        .fetch_metadata(&fetcher_message.game_name)
-                        .await
-                        .unwrap_or_default();
-
-                    let metadata: GameMetaData = igdb_metadata.into();
-                    let metadata = metadata
-                        .merge(metacritic_metadata.into())
-                        .merge(steam_metadata.into());
-
-                    if let Err(e) = fetcher_message.sender.send(metadata) {
-                        tracing::debug!("Could not send back result: {:?}", e);
-                    };
-                }
+                let high_priority =
+                    process_messages(hprx, providers.clone(), true, shutdown.clone());
+                let low_priority = process_messages(lprx, providers, false, shutdown);
+                tokio::try_join!(high_priority, low_priority)?;
+                tracing::info!("Metadata fetcher stopped");
+                Ok(())
             }
         });
         Self {
             join_handler,
             low_priority: lptx,
             high_priority: hptx,
+            providers,
+        }
+    }
+}
+
+// Fetch the metadata of the messages received, at most
+// MAX_CONCURRENT_FETCHES at the same time
+async fn process_messages(
+    mut rx: MspcReceiver<FetcherMessage>,
+    providers: ProviderSet,
+    high_priority: bool,
+    mut shutdown: WatchReceiver<bool>,
+) -> anyhow::Result<()> {
+    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_FETCHES));
+    loop {
+        let permit = tokio::select! {
+            permit = semaphore.clone().acquire_owned() => permit?,
+            _ = shutdown.changed() => break,
+        };
+        let fetcher_message: FetcherMessage = tokio::select! {
+            _ = shutdown.changed() => break,
+            Some(message) = rx.recv() => message,
+            else => break,
+        };
+        let providers = providers.load();
+        tokio::spawn(async move {
+            let result = fetch_metadata(providers, fetcher_message.keys, high_priority).await;
+            if let Err(e) = fetcher_message.sender.send(result) {
+                tracing::debug!("Could not send back result: {:?}", e);
+            };
+            drop(permit);
+        });
+    }
+    // let the fetches in progress finish so their results are cached
+    let timeout = tokio::time::Duration::from_secs(PROVIDER_TIMEOUT);
+    let all_permits = semaphore.acquire_many(MAX_CONCURRENT_FETCHES as u32);
+    if tokio::time::timeout(timeout, all_permits).await.is_err() {
+        tracing::debug!("Some metadata fetches did not finish in time");
+    }
+    Ok(())
+}
+
+// Query the providers concurrently and merge their
+// metadata following the order of priority
+async fn fetch_metadata(
+    providers: Arc<Vec<LimitedProvider>>,
+    keys: MetadataKeys,
+    high_priority: bool,
+) -> FetchResult {
+    let mut join_set = JoinSet::new();
+    for (priority, limited_provider) in providers.iter().enumerate() {
+        let Some(key) = keys.get(limited_provider.provider.key_kind()) else {
+            continue;
+        };
+        let providers = providers.clone();
+        join_set.spawn(async move {
+            let LimitedProvider { provider, limiter } = &providers[priority];
+            limiter.acquire(high_priority).await;
+            let timeout = tokio::time::Duration::from_secs(PROVIDER_TIMEOUT);
+            let result = match tokio::time::timeout(timeout, provider.fetch(&key)).await {
+                Ok(Ok(provider_metadata)) => Ok(provider_metadata.with_source(provider.name())),
+                Ok(Err(e)) => {
+                    tracing::debug!(
+                        "Error while fetching {} metadata for {}: {e}",
+                        provider.name(),
+                        key
+                    );
+                    Err(FetchErrorKind::from_error(&e))
+                }
+                Err(_) => {
+                    tracing::debug!(
+                        "{} did not answer within {} seconds",
+                        provider.name(),
+                        PROVIDER_TIMEOUT
+                    );
+                    Err(FetchErrorKind::Network)
+                }
+            };
+            (priority, (provider.name(), result))
+        });
+    }
+    let mut results: Vec<(usize, ProviderAnswer)> = vec![];
+    while let Some(joined) = join_set.join_next().await {
+        match joined {
+            Ok(result) => results.push(result),
+            Err(e) => tracing::warn!("A metadata fetch panicked: {e}"),
+        }
+    }
+    results.sort_by_key(|(priority, _)| *priority);
+    // the lookup failed only if every provider queried failed,
+    // the error with the shortest retry delay is kept
+    if results.iter().all(|(_, (_, result))| result.is_err()) {
+        let kind = results
+            .into_iter()
+            .filter_map(|(_, (_, result))| result.err())
+            .min_by_key(|kind| kind.retry_delay());
+        // no provider has a key for that game
+        return match kind {
+            Some(kind) => Err(kind),
+            None => Ok(FetchedMetaData::default()),
+        };
+    }
+    Ok(FetchedMetaData {
+        answers: results.into_iter().map(|(_, answer)| answer).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        db::data::GameMetaData,
+        limiter::RateLimit,
+        provider::{KeyKind, ProviderError, ProviderKey},
+    };
+    use async_trait::async_trait;
+
+    struct MockProvider {
+        name: &'static str,
+        delay: u64,
+        summary: Option<String>,
+    }
+
+    #[async_trait]
+    impl MetadataProvider for MockProvider {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+        fn key_kind(&self) -> KeyKind {
+            KeyKind::GameName
         }
+        fn rate_limit(&self) -> RateLimit {
+            RateLimit {
+                requests_per_second: 100.0,
+                requests_per_fetch: 1,
+            }
+        }
+        async fn fetch(&self, _key: &ProviderKey) -> anyhow::Result<GameMetaData> {
+            tokio::time::sleep(tokio::time::Duration::from_millis(self.delay)).await;
+            match self.summary {
+                Some(ref summary) => Ok(GameMetaData {
+                    summary: Some(summary.clone()),
+                    ..Default::default()
+                }),
+                None => Err(ProviderError::NotFound("unknown game".into()).into()),
+            }
+        }
+    }
+
+    fn get_providers(providers: Vec<MockProvider>) -> Arc<Vec<LimitedProvider>> {
+        Arc::new(
+            providers
+                .into_iter()
+                .map(|provider| LimitedProvider {
+                    limiter: RateLimiter::new(provider.rate_limit()),
+                    provider: Arc::new(provider),
+                })
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_fetch_metadata_keeps_priority() {
+        // the first provider answers last but still wins
+        let providers = get_providers(vec![
+            MockProvider {
+                name: "Slow",
+                delay: 50,
+                summary: Some("slow".into()),
+            },
+            MockProvider {
+                name: "Fast",
+                delay: 0,
+                summary: Some("fast".into()),
+            },
+        ]);
+        let metadata = fetch_metadata(providers, MetadataKeys::default(), true)
+            .await
+            .unwrap()
+            .metadata(None);
+        assert_eq!(metadata.summary, Some("slow".into()));
+        assert_eq!(metadata.provenance.summary.unwrap().provider, "Slow");
+    }
+    #[tokio::test]
+    async fn test_fetch_metadata_fails_when_every_provider_fails() {
+        let providers = get_providers(vec![MockProvider {
+            name: "Broken",
+            delay: 0,
+            summary: None,
+        }]);
+        let result = fetch_metadata(providers, MetadataKeys::default(), true).await;
+        assert_eq!(result.unwrap_err(), FetchErrorKind::NotFound);
+    }
+    #[tokio::test]
+    async fn test_fetch_metadata_records_failed_providers() {
+        let providers = get_providers(vec![
+            MockProvider {
+                name: "Broken",
+                delay: 0,
+                summary: None,
+            },
+            MockProvider {
+                name: "Working",
+                delay: 0,
+                summary: Some("working".into()),
+            },
+        ]);
+        let fetched = fetch_metadata(providers, MetadataKeys::default(), true)
+            .await
+            .unwrap();
+        assert_eq!(fetched.metadata(None).summary, Some("working".into()));
+        let failures = fetched.failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].provider, "Broken");
+        assert_eq!(failures[0].kind, FetchErrorKind::NotFound);
     }
 }
@@ -0,0 +1,308 @@
+use axum::{
+    extract::{
+        rejection::{PathRejection, QueryRejection},
+        Path, Query, State,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use libpobsd::Game;
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::{
+    app::{pagination::Page, representation::GameRepresentation},
+    db::{changelog::ChangeLog, connector::DbCon, responses::AppDbResponse, stats::GameStats},
+    handlers::list::Params,
+    utils::{DbState, ParseError},
+};
+
+#[derive(Debug, Serialize)]
+pub struct ApiErrorBody {
+    pub code: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    pub status: StatusCode,
+    pub error: ApiErrorBody,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            error: ApiErrorBody {
+                code,
+                message: message.into(),
+            },
+        }
+    }
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+    pub fn internal() -> Self {
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "Internal error",
+        )
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self)).into_response()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct GameListResponse {
+    pub games: Vec<Game>,
+    pub page: Option<Page>,
+}
+
+pub fn get_api_router() -> Router<Arc<DbCon>> {
+    Router::new()
+        .route("/games", get(game_list))
+        .route("/games/ids", get(game_ids))
+        .route("/games/recent", get(recent_games))
+        .route("/games/random", get(random_game))
+        .route("/games/:game_id", get(game_details))
+        .route("/stats", get(game_stats))
+        .route("/changes", get(changes))
+        .route("/database", get(db_state))
+        .route("/diagnostics", get(parse_errors))
+        .fallback(not_found)
+}
+
+async fn get_game_representation(
+    db_con: &DbCon,
+    game_id: u32,
+) -> Result<GameRepresentation, ApiError> {
+    match db_con.get_game_representation(game_id).await {
+        AppDbResponse::GameRepresentation(game_representation) => Ok(game_representation),
+        AppDbResponse::NoGame => Err(ApiError::not_found(format!(
+            "No game with the id {}",
+            game_id
+        ))),
+        _ => Err(ApiError::internal()),
+    }
+}
+
+pub async fn game_details(
+    State(db_con): State<Arc<DbCon>>,
+    game_id: Result<Path<u32>, PathRejection>,
+) -> Result<Json<GameRepresentation>, ApiError> {
+    let game_id = match game_id {
+        Ok(Path(game_id)) => game_id,
+        Err(e) => return Err(ApiError::bad_request(e.body_text())),
+    };
+    get_game_representation(&db_con, game_id).await.map(Json)
+}
+
+pub async fn game_list(
+    State(db_con): State<Arc<DbCon>>,
+    params: Result<Query<Params>, QueryRejection>,
+) -> Result<Json<GameListResponse>, ApiError> {
+    let params = match params {
+        Ok(Query(params)) => params,
+        Err(e) => return Err(ApiError::bad_request(e.body_text())),
+    };
+    let page = params.page.unwrap_or(1);
+    if page == 0 {
+        return Err(ApiError::bad_request("Pages start at 1"));
+    }
+    let selection = match params.selection() {
+        Ok(selection) => selection,
+        Err(e) => return Err(ApiError::bad_request(format!("Invalid filter: {}", e))),
+    };
+    match db_con
+        .get_game_list_paginated(&selection, page, params.per_page)
+        .await
+    {
+        AppDbResponse::GameListPaginated(games, page) => Ok(Json(GameListResponse {
+            games,
+            page: Some(page),
+        })),
+        AppDbResponse::NoGame => Ok(Json(GameListResponse {
+            games: vec![],
+            page: None,
+        })),
+        AppDbResponse::NoPage => Err(ApiError::not_found(format!(
+            "The page {} does not exist",
+            page
+        ))),
+        _ => Err(ApiError::internal()),
+    }
+}
+
+pub async fn game_ids(State(db_con): State<Arc<DbCon>>) -> Result<Json<Vec<u32>>, ApiError> {
+    match db_con.get_game_ids().await {
+        AppDbResponse::GameIds(game_ids) => {
+            let mut game_ids: Vec<u32> = game_ids.into_iter().collect();
+            game_ids.sort();
+            Ok(Json(game_ids))
+        }
+        _ => Err(ApiError::internal()),
+    }
+}
+
+pub async fn recent_games(
+    State(db_con): State<Arc<DbCon>>,
+) -> Result<Json<Vec<GameRepresentation>>, ApiError> {
+    match db_con.get_recent_games().await {
+        AppDbResponse::RecentGames(game_representations) => Ok(Json(game_representations)),
+        _ => Err(ApiError::internal()),
+    }
+}
+
+pub async fn random_game(
+    State(db_con): State<Arc<DbCon>>,
+) -> Result<Json<GameRepresentation>, ApiError> {
+    match db_con.get_random_uid().await {
+        AppDbResponse::RandomUid(game_id) => {
+            get_game_representation(&db_con, game_id).await.map(Json)
+        }
+        _ => Err(ApiError::internal()),
+    }
+}
+
+pub async fn game_stats(State(db_con): State<Arc<DbCon>>) -> Result<Json<GameStats>, ApiError> {
+    match db_con.get_game_stats().await {
+        AppDbResponse::GameStats(game_stats) => Ok(Json(game_stats)),
+        _ => Err(ApiError::internal()),
+    }
+}
+
+pub async fn changes(State(db_con): State<Arc<DbCon>>) -> Result<Json<Vec<ChangeLog>>, ApiError> {
+    match db_con.get_changelog().await {
+        AppDbResponse::ChangeLog(changelogs) => Ok(Json(changelogs)),
+        _ => Err(ApiError::internal()),
+    }
+}
+
+pub async fn db_state(State(db_con): State<Arc<DbCon>>) -> Result<Json<DbState>, ApiError> {
+    match db_con.get_db_state().await {
+        AppDbResponse::DbState(db_state) => Ok(Json(db_state)),
+        _ => Err(ApiError::internal()),
+    }
+}
+
+pub async fn parse_errors(
+    State(db_con): State<Arc<DbCon>>,
+) -> Result<Json<Vec<ParseError>>, ApiError> {
+    match db_con.get_db_state().await {
+        AppDbResponse::DbState(db_state) => Ok(Json(db_state.parse_errors)),
+        _ => Err(ApiError::internal()),
+    }
+}
+
+pub async fn not_found() -> ApiError {
+    ApiError::not_found("Unknown endpoint")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cache::MetaDataCache,
+        db::AppDb,
+        test_utils::{game_id, test_db},
+    };
+    use axum::{body::Body, http::Request};
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    fn get_router() -> Router {
+        let (tx, _) = tokio::sync::mpsc::channel(10);
+        let db_con = AppDb::new(
+            test_db(),
+            MetaDataCache::default(),
+            3600,
+            Default::default(),
+            Default::default(),
+            tx.clone(),
+            tx,
+        )
+        .launch();
+        Router::new()
+            .nest("/api/v1", get_api_router())
+            .with_state(Arc::new(db_con))
+    }
+    async fn get(uri: &str) -> (StatusCode, Value) {
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = get_router().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_game_list() {
+        let (status, body) = get("/api/v1/games?per_page=4&page=2").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["games"].as_array().unwrap().len(), 4);
+        assert_eq!(body["page"]["current_page"], 2);
+        assert_eq!(body["page"]["last_page"], 3);
+        // no game is not an error
+        let (status, body) = get("/api/v1/games?filter=name%3Dnothingmatches").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({"games": [], "page": null}));
+    }
+    #[tokio::test]
+    async fn test_game_list_errors() {
+        let (status, body) = get("/api/v1/games?page=0").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body,
+            json!({"error": {"code": "bad_request", "message": "Pages start at 1"}})
+        );
+        let (status, body) = get("/api/v1/games?page=99").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "not_found");
+        let (status, body) = get("/api/v1/games?page=first").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "bad_request");
+        let (status, body) = get("/api/v1/games?filter=foo%3Dbar").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Invalid filter"));
+    }
+    #[tokio::test]
+    async fn test_game_details() {
+        let game_id = game_id(&test_db(), "The Adventures of Mr. Hat");
+        let (status, body) = get(&format!("/api/v1/games/{}", game_id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["game"]["name"], "The Adventures of Mr. Hat");
+        let (status, body) = get("/api/v1/games/notanid").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "bad_request");
+        let (status, body) = get("/api/v1/games/1").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body,
+            json!({"error": {"code": "not_found", "message": "No game with the id 1"}})
+        );
+    }
+    #[tokio::test]
+    async fn test_unknown_endpoint() {
+        let (status, body) = get("/api/v1/nothing").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body,
+            json!({"error": {"code": "not_found", "message": "Unknown endpoint"}})
+        );
+    }
+}
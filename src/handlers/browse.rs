@@ -0,0 +1,151 @@
+use askama::Template;
+use axum::{
+    extract::{Query, State},
+    response::Html,
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::{
+    db::{
+        connector::DbCon,
+        facets::FacetValue,
+        query::{Field, GameQuery, Op},
+        responses::AppDbResponse,
+    },
+    handlers::{entity::entity_path, errors::InternalErrorTemplate},
+};
+
+// the field, path and title of each index page
+const PAGES: [(Field, &str, &str); 7] = [
+    (Field::Engine, "/engines", "Engines"),
+    (Field::Runtime, "/runtimes", "Runtimes"),
+    (Field::Dev, "/devs", "Developers"),
+    (Field::Publi, "/publishers", "Publishers"),
+    (Field::Genre, "/genres", "Genres"),
+    (Field::Tag, "/tags", "Tags"),
+    (Field::Year, "/years", "Years"),
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BrowseSort {
+    #[default]
+    Name,
+    Count,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BrowseParams {
+    pub sort: Option<BrowseSort>,
+}
+
+// The values starting with the same letter, the ones
+// not starting with a letter are grouped under #
+pub struct LetterGroup {
+    letter: String,
+    values: Vec<FacetValue>,
+}
+
+fn letter(label: &str) -> String {
+    match label.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => c.to_ascii_uppercase().to_string(),
+        _ => "#".into(),
+    }
+}
+
+fn group_by_letter(mut values: Vec<FacetValue>) -> Vec<LetterGroup> {
+    values.sort_by_cached_key(|value| (letter(&value.label), value.label.to_lowercase()));
+    let mut groups: Vec<LetterGroup> = vec![];
+    for value in values {
+        let letter = letter(&value.label);
+        match groups.last_mut() {
+            Some(group) if group.letter == letter => group.values.push(value),
+            _ => groups.push(LetterGroup {
+                letter,
+                values: vec![value],
+            }),
+        }
+    }
+    groups
+}
+
+impl LetterGroup {
+    // # cannot be used as a fragment
+    fn anchor(&self) -> &str {
+        match self.letter.as_str() {
+            "#" => "other",
+            letter => letter,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "browse.html")]
+struct BrowseTemplate {
+    title: &'static str,
+    field: Field,
+    path: &'static str,
+    // the values link to their own page when there is one
+    entity_path: Option<&'static str>,
+    sort: BrowseSort,
+    total: usize,
+    // a single group without letter when sorted by count
+    groups: Vec<LetterGroup>,
+}
+
+impl BrowseTemplate {
+    // The query string of the list of the games with that value
+    fn list_query(&self, value: &str) -> String {
+        let query = GameQuery::Compare {
+            field: self.field,
+            op: Op::Exact,
+            values: vec![value.into()],
+        };
+        serde_urlencoded::to_string([("filter", query.to_string())]).unwrap_or_default()
+    }
+}
+
+async fn browse(
+    (field, path, title): (Field, &'static str, &'static str),
+    State(db_con): State<Arc<DbCon>>,
+    Query(params): Query<BrowseParams>,
+) -> Result<Html<String>, Html<String>> {
+    match db_con.get_facet(field).await {
+        AppDbResponse::Facet(facet) => {
+            let sort = params.sort.unwrap_or_default();
+            let total = facet.values.len();
+            let groups = match sort {
+                BrowseSort::Name => group_by_letter(facet.values),
+                BrowseSort::Count => {
+                    let mut values = facet.values;
+                    // the sort is stable so values keep their order for a same count
+                    values.sort_by_key(|value| std::cmp::Reverse(value.count));
+                    vec![LetterGroup {
+                        letter: String::new(),
+                        values,
+                    }]
+                }
+            };
+            let template = BrowseTemplate {
+                title,
+                field,
+                path,
+                entity_path: entity_path(&field),
+                sort,
+                total,
+                groups,
+            };
+            Ok(Html(template.to_string()))
+        }
+        _ => Err(Html(InternalErrorTemplate {}.to_string())),
+    }
+}
+
+pub fn get_browse_router() -> Router<Arc<DbCon>> {
+    PAGES.into_iter().fold(Router::new(), |router, page| {
+        router.route(page.1, get(move |state, query| browse(page, state, query)))
+    })
+}
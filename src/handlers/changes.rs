@@ -0,0 +1,23 @@
+use askama::Template;
+use axum::{extract::State, response::Html};
+use std::sync::Arc;
+
+use crate::{
+    db::{changelog::ChangeLog, connector::DbCon, responses::AppDbResponse},
+    handlers::errors::InternalErrorTemplate,
+};
+
+#[derive(Template)]
+#[template(path = "changes.html")]
+struct ChangesTemplate {
+    changelogs: Vec<ChangeLog>,
+}
+
+pub async fn changes(State(db_con): State<Arc<DbCon>>) -> Result<Html<String>, Html<String>> {
+    match db_con.get_changelog().await {
+        AppDbResponse::ChangeLog(changelogs) => {
+            Ok(Html(ChangesTemplate { changelogs }.to_string()))
+        }
+        _ => Err(Html(InternalErrorTemplate {}.to_string())),
+    }
+}
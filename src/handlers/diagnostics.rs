@@ -0,0 +1,22 @@
+use askama::Template;
+use axum::{extract::State, response::Html};
+use std::sync::Arc;
+
+use crate::{
+    db::{connector::DbCon, responses::AppDbResponse},
+    handlers::errors::InternalErrorTemplate,
+    utils::DbState,
+};
+
+#[derive(Template)]
+#[template(path = "diagnostics.html")]
+struct DiagnosticsTemplate {
+    db_state: DbState,
+}
+
+pub async fn diagnostics(State(db_con): State<Arc<DbCon>>) -> Result<Html<String>, Html<String>> {
+    match db_con.get_db_state().await {
+        AppDbResponse::DbState(db_state) => Ok(Html(DiagnosticsTemplate { db_state }.to_string())),
+        _ => Err(Html(InternalErrorTemplate {}.to_string())),
+    }
+}
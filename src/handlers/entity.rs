@@ -0,0 +1,103 @@
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    response::Html,
+    routing::get,
+    Router,
+};
+use libpobsd::Status;
+use std::sync::Arc;
+
+use crate::{
+    db::{
+        connector::DbCon,
+        entity::Entity,
+        query::{Field, GameQuery, Op},
+        responses::AppDbResponse,
+    },
+    handlers::errors::{ErrorNoGamesTemplate, InternalErrorTemplate},
+};
+
+// the field, path and title of each entity page,
+// the path also being the one of the index page
+const PAGES: [(Field, &str, &str); 4] = [
+    (Field::Engine, "/engines", "Engine"),
+    (Field::Runtime, "/runtimes", "Runtime"),
+    (Field::Dev, "/devs", "Developer"),
+    (Field::Publi, "/publishers", "Publisher"),
+];
+
+// The path the entity pages of that field are found under
+pub fn entity_path(field: &Field) -> Option<&'static str> {
+    PAGES
+        .iter()
+        .find(|(page_field, _, _)| page_field == field)
+        .map(|(_, path, _)| *path)
+}
+
+fn compare(field: Field, value: &str) -> GameQuery {
+    GameQuery::Compare {
+        field,
+        op: Op::Exact,
+        values: vec![value.into()],
+    }
+}
+
+#[derive(Template)]
+#[template(path = "entity.html")]
+struct EntityTemplate {
+    title: &'static str,
+    path: &'static str,
+    entity: Entity,
+}
+
+impl EntityTemplate {
+    fn query(&self) -> GameQuery {
+        compare(self.entity.field, &self.entity.name)
+    }
+    // The query string of the list of the games of the entity
+    fn list_query(&self) -> String {
+        serde_urlencoded::to_string([("filter", self.query().to_string())]).unwrap_or_default()
+    }
+    // The same restricted to the games with that status
+    fn status_query(&self, status: &str) -> String {
+        let query = GameQuery::and(vec![self.query(), compare(Field::Status, status)]);
+        serde_urlencoded::to_string([("filter", query.to_string())]).unwrap_or_default()
+    }
+    fn related_path(&self, field: &Field) -> &'static str {
+        entity_path(field).unwrap_or_default()
+    }
+}
+
+async fn entity_page(
+    (field, path, title): (Field, &'static str, &'static str),
+    State(db_con): State<Arc<DbCon>>,
+    Path(name): Path<String>,
+) -> Result<Html<String>, Html<String>> {
+    match db_con.get_entity(field, &name).await {
+        AppDbResponse::Entity(entity) => {
+            let template = EntityTemplate {
+                title,
+                path,
+                entity,
+            };
+            Ok(Html(template.to_string()))
+        }
+        AppDbResponse::NoGame => Ok(Html(
+            ErrorNoGamesTemplate {
+                suggestions: vec![],
+            }
+            .to_string(),
+        )),
+        _ => Err(Html(InternalErrorTemplate {}.to_string())),
+    }
+}
+
+pub fn get_entity_router() -> Router<Arc<DbCon>> {
+    PAGES.into_iter().fold(Router::new(), |router, page| {
+        router.route(
+            &format!("{}/:name", page.1),
+            get(move |state, name| entity_page(page, state, name)),
+        )
+    })
+}
@@ -1,5 +1,7 @@
 use askama::Template;
 
+use crate::db::changelog::GameRef;
+
 #[derive(Template)]
 #[template(path = "500.html")]
 pub struct InternalErrorTemplate {}
@@ -8,6 +10,15 @@ pub struct InternalErrorTemplate {}
 #[template(path = "404_no_game.html")]
 pub struct ErrorNoGameTemplate {}
 
+#[derive(Template)]
+#[template(path = "400_invalid_query.html")]
+pub struct InvalidQueryTemplate {
+    pub message: String,
+}
+
 #[derive(Template)]
 #[template(path = "404_no_games.html")]
-pub struct ErrorNoGamesTemplate {}
+pub struct ErrorNoGamesTemplate {
+    // closest game names when the query may have a typo
+    pub suggestions: Vec<GameRef>,
+}
@@ -3,7 +3,8 @@ use std::sync::Arc;
 use askama::Template;
 use axum::{
     extract::{rejection::PathRejection, Path, State},
-    response::Html,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
 };
 // Status is used in template
 use libpobsd::{Game, Status};
@@ -14,11 +15,17 @@ use crate::{
     handlers::errors::ErrorNoGameTemplate,
 };
 
+// in seconds, after that delay the page stops
+// waiting for the metadata being fetched
+const METADATA_POLL_TIMEOUT: u64 = 45;
+
 #[derive(Template)]
 #[template(path = "game_details.html")]
 pub struct GameDetailsTemplate {
     game: Game,
     metadata: Option<GameMetaData>,
+    metadata_pending: bool,
+    poll_timeout: u64,
 }
 
 impl GameDetailsTemplate {
@@ -26,10 +33,19 @@ impl GameDetailsTemplate {
         GameDetailsTemplate {
             game: game_representation.game,
             metadata: game_representation.metadata,
+            metadata_pending: game_representation.metadata_pending,
+            poll_timeout: METADATA_POLL_TIMEOUT,
         }
     }
 }
 
+// Fragment used to fill in the metadata of a game page
+#[derive(Template)]
+#[template(path = "game_metadata.html")]
+pub struct GameMetaDataTemplate {
+    game_metadata: GameMetaData,
+}
+
 pub async fn game_details(
     State(db_con): State<Arc<DbCon>>,
     game_id: Result<Path<u32>, PathRejection>,
@@ -43,20 +59,43 @@ pub async fn game_details(
             let body = GameDetailsTemplate::new(game_representation);
             Ok(Html(body.to_string()))
         }
-        AppDbResponse::Pending(rx) => match rx.await {
-            Ok(metadata) => {
-                db_con.insert_metadata(game_id, metadata).await;
-                match db_con.get_game_representation(game_id).await {
-                    AppDbResponse::GameRepresentation(game_representation) => {
-                        let body = GameDetailsTemplate::new(game_representation);
-                        Ok(Html(body.to_string()))
-                    }
-                    _ => Err(Html(ErrorNoGameTemplate {}.to_string())),
+        _ => Err(Html(ErrorNoGameTemplate {}.to_string())),
+    }
+}
+
+// Answers 200 with the fragment when the metadata are available,
+// 202 while they are being fetched and 204 when there are none
+pub async fn game_metadata(
+    State(db_con): State<Arc<DbCon>>,
+    game_id: Result<Path<u32>, PathRejection>,
+) -> Response {
+    let game_id = match game_id {
+        Ok(Path(game_id)) => game_id,
+        Err(_) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Html(ErrorNoGameTemplate {}.to_string()),
+            )
+                .into_response()
+        }
+    };
+    match db_con.get_game_representation(game_id).await {
+        AppDbResponse::GameRepresentation(game_representation) => {
+            match game_representation.metadata {
+                Some(game_metadata) => {
+                    Html(GameMetaDataTemplate { game_metadata }.to_string()).into_response()
+                }
+                None if game_representation.metadata_pending => {
+                    StatusCode::ACCEPTED.into_response()
                 }
+                None => StatusCode::NO_CONTENT.into_response(),
             }
-            _ => Err(Html(ErrorNoGameTemplate {}.to_string())),
-        },
-        _ => Err(Html(ErrorNoGameTemplate {}.to_string())),
+        }
+        _ => (
+            StatusCode::NOT_FOUND,
+            Html(ErrorNoGameTemplate {}.to_string()),
+        )
+            .into_response(),
     }
 }
 
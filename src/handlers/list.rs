@@ -1,7 +1,8 @@
 use askama::Template;
 use axum::{
     extract::{Form, Query, State},
-    response::Html,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
 };
 use libpobsd::{Game, Status};
 use serde::Deserialize;
@@ -9,11 +10,18 @@ use std::sync::Arc;
 
 use crate::{
     app::{pagination::Page, representation::GameRepresentation},
-    db::{connector::DbCon, responses::AppDbResponse},
+    db::{
+        connector::DbCon,
+        facets::Facet,
+        query::{Field, GameQuery, Op, QueryError},
+        responses::AppDbResponse,
+        snapshot::GameSelection,
+        sort::{Sort, SortKey, SortOrder},
+    },
     utils::GameFilterWrapper,
 };
 
-use crate::handlers::errors::{ErrorNoGamesTemplate, InternalErrorTemplate};
+use crate::handlers::errors::{ErrorNoGamesTemplate, InternalErrorTemplate, InvalidQueryTemplate};
 
 #[derive(Deserialize, Debug)]
 pub struct Search {
@@ -26,32 +34,154 @@ pub struct GameListTemplate {
     games: Vec<Game>,
     paginator: Page,
     query_str: String,
+    facets: Vec<Facet>,
+    // used to build the links refining the list
+    params: Params,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Params {
     pub page: Option<usize>,
+    // bounded by the configuration
+    pub per_page: Option<usize>,
+    // full text search, combined with the filters
+    pub q: Option<String>,
+    // query language, the other filters are ignored when set
+    #[serde(rename = "filter")]
+    pub query: Option<String>,
+    pub sort: Option<SortKey>,
+    pub order: Option<SortOrder>,
     #[serde(flatten)]
     pub filter: GameFilterWrapper,
 }
 
-pub async fn game_list(
-    State(db_con): State<Arc<DbCon>>,
-    Query(params): Query<Params>,
-) -> Result<Html<String>, Html<String>> {
+impl Params {
+    pub fn search(&self) -> Option<&str> {
+        self.q
+            .as_deref()
+            .map(str::trim)
+            .filter(|query| !query.is_empty())
+    }
+    fn filter_query(&self) -> Result<Option<GameQuery>, QueryError> {
+        match self.query.as_deref().map(str::trim) {
+            Some(text) if !text.is_empty() => GameQuery::parse(text).map(Some),
+            _ => Ok(None),
+        }
+    }
+    // The query, or the filters of the query string written as one
+    pub fn query(&self) -> Result<Option<GameQuery>, QueryError> {
+        match self.filter_query()? {
+            Some(query) => Ok(Some(query)),
+            None => Ok(GameQuery::from_filter(&self.filter)),
+        }
+    }
+    // Games are sorted by name when only the order is given
+    pub fn sort(&self) -> Option<Sort> {
+        if self.sort.is_none() && self.order.is_none() {
+            return None;
+        }
+        Some(Sort {
+            key: self.sort.unwrap_or_default(),
+            order: self.order.unwrap_or_default(),
+        })
+    }
+    pub fn selection(&self) -> Result<GameSelection, QueryError> {
+        Ok(GameSelection {
+            search: self.search().map(String::from),
+            query: self.query()?,
+            sort: self.sort(),
+        })
+    }
+    // Everything but the page so the pagination links keep the list as it is
+    pub fn query_string(&self) -> String {
+        let mut query_string = vec![];
+        if let Some(search) = self.search() {
+            query_string.push(serde_urlencoded::to_string([("q", search)]).unwrap_or_default());
+        }
+        match self.filter_query() {
+            Ok(Some(query)) => query_string.push(
+                serde_urlencoded::to_string([("filter", query.to_string())]).unwrap_or_default(),
+            ),
+            _ => query_string.push(self.filter.query_string()),
+        }
+        if let Some(sort) = self.sort() {
+            query_string.push(sort.query_string());
+        }
+        if let Some(per_page) = self.per_page {
+            query_string.push(format!("per_page={}", per_page));
+        }
+        query_string.retain(|part| !part.is_empty());
+        query_string.join("&")
+    }
+    // The query string of the list restricted to the games with that value
+    pub fn refine(&self, field: &Field, value: &str) -> String {
+        let refinement = GameQuery::Compare {
+            field: *field,
+            op: Op::Exact,
+            values: vec![value.into()],
+        };
+        let query = match self.query() {
+            Ok(Some(query)) if query.requires(&refinement) => query,
+            Ok(Some(query)) => GameQuery::and(vec![query, refinement]),
+            _ => refinement,
+        };
+        let params = Params {
+            page: None,
+            per_page: self.per_page,
+            q: self.q.clone(),
+            query: Some(query.to_string()),
+            sort: self.sort,
+            order: self.order,
+            filter: GameFilterWrapper::default(),
+        };
+        params.query_string()
+    }
+    // the text a typo could have been made in
+    pub fn fuzzy_text(&self) -> Option<&str> {
+        self.search().or(self.filter.name.as_deref())
+    }
+}
+
+pub async fn game_list(State(db_con): State<Arc<DbCon>>, Query(params): Query<Params>) -> Response {
     let page = params.page.unwrap_or(1);
-    let filter: GameFilterWrapper = params.filter.clone();
-    match db_con.get_game_list_paginated_filtered(page, filter).await {
+    let selection = match params.selection() {
+        Ok(selection) => selection,
+        Err(e) => {
+            let template = InvalidQueryTemplate {
+                message: e.to_string(),
+            };
+            return (StatusCode::BAD_REQUEST, Html(template.to_string())).into_response();
+        }
+    };
+    match db_con
+        .get_game_list_paginated(&selection, page, params.per_page)
+        .await
+    {
         AppDbResponse::GameListPaginated(games, page) => {
+            let facets = match db_con.get_facets(&selection).await {
+                AppDbResponse::Facets(facets) => facets,
+                _ => vec![],
+            };
             let gmt = GameListTemplate {
                 games,
                 paginator: page,
-                query_str: params.filter.query_string(),
+                query_str: params.query_string(),
+                facets,
+                params,
             };
-            Ok(Html(gmt.to_string()))
+            Html(gmt.to_string()).into_response()
         }
-        AppDbResponse::NoGame => Ok(Html(ErrorNoGamesTemplate {}.to_string())),
-        _ => Err(Html(InternalErrorTemplate {}.to_string())),
+        AppDbResponse::NoGame | AppDbResponse::NoPage => {
+            let suggestions = match params.fuzzy_text() {
+                Some(text) => match db_con.get_suggestions(text).await {
+                    AppDbResponse::Suggestions(suggestions) => suggestions,
+                    _ => vec![],
+                },
+                None => vec![],
+            };
+            Html(ErrorNoGamesTemplate { suggestions }.to_string()).into_response()
+        }
+        _ => Html(InternalErrorTemplate {}.to_string()).into_response(),
     }
 }
 
@@ -74,11 +204,17 @@ pub async fn news(State(db_con): State<Arc<DbCon>>) -> Result<Html<String>, Html
 }
 
 pub async fn game_list_search(Form(search): Form<Search>) -> axum::response::Redirect {
-    let pattern = search.pattern;
+    let pattern = search.pattern.trim();
     if pattern.is_empty() {
         axum::response::Redirect::to("")
     } else {
-        let filter = GameFilterWrapper::from_pattern(&pattern);
-        axum::response::Redirect::to(&format!("?{}", &filter.query_string()))
+        // the text is used as a filter when it looks like one
+        let key = if GameQuery::is_query(pattern) {
+            "filter"
+        } else {
+            "q"
+        };
+        let query_str = serde_urlencoded::to_string([(key, pattern)]).unwrap_or_default();
+        axum::response::Redirect::to(&format!("?{}", query_str))
     }
 }
@@ -1,5 +1,10 @@
 pub mod about;
+pub mod api;
 pub mod assets;
+pub mod browse;
+pub mod changes;
+pub mod diagnostics;
+pub mod entity;
 pub mod errors;
 pub mod game;
 pub mod list;
@@ -26,10 +31,16 @@ pub fn get_router(db_con: Arc<DbCon>) -> Router {
         .route("/random", get(game::game_details_random))
         .route("/rss", get(rss::rss_feed))
         .route("/news", get(list::news))
+        .route("/changes", get(changes::changes))
+        .route("/diagnostics", get(diagnostics::diagnostics))
         .route("/:game_id", get(game::game_details))
+        .route("/:game_id/metadata", get(game::game_metadata))
         .route("/stats_table", get(stats::game_stats_table))
         .route("/stats_chart", get(stats::game_stats_chart))
         .route("/about", get(about::about_page()))
+        .merge(browse::get_browse_router())
+        .merge(entity::get_entity_router())
+        .nest("/api/v1", api::get_api_router())
         .with_state(db_con)
         .layer(TraceLayer::new_for_http())
 }
@@ -1,7 +1,9 @@
-use anyhow::anyhow;
-use igdb::client::{CoversClient, GamesClient, IGDBClient, ScreenshotsClient};
+use igdb::{
+    client::{CoversClient, GamesClient, IGDBClient, ScreenshotsClient},
+    request_builder::{Equality, RequestBuilder},
+};
 
-use crate::{db::data::GameMetaData, igdb::token::IgdbToken};
+use crate::{db::data::GameMetaData, igdb::token::IgdbToken, provider::ProviderError};
 
 #[derive(Debug, Clone, Default)]
 pub struct IgdbMetaData {
@@ -30,10 +32,19 @@ impl From<IgdbMetaData> for GameMetaData {
             screenshots: value.screenshots,
             cover,
             metacritic: None,
+            ..Default::default()
         }
     }
 }
 
+// The igdb crate boxes its errors, which come from its own version
+// of reqwest, and decodes every answer as a list so an error status
+// from IGDB ends up as a decoding error: none of them means the game
+// is missing, they are reported as IGDB being unavailable
+fn igdb_error(error: Box<dyn std::error::Error + Send + Sync>) -> anyhow::Error {
+    ProviderError::Unavailable(format!("IGDB request failed: {}", error)).into()
+}
+
 pub struct IgdbClient {
     pub client_id: String,
     pub client_secret: String,
@@ -59,58 +70,83 @@ impl IgdbClient {
         })
     }
 
-    pub async fn fetch_metadata(&mut self, igdb_id: usize) -> anyhow::Result<IgdbMetaData> {
-        self.refresh_token().await?;
+    pub async fn fetch_metadata(&self, igdb_id: usize) -> anyhow::Result<IgdbMetaData> {
         tracing::debug!("fetching: {:?}", igdb_id);
-        match self.games_client.get_first_by_id(igdb_id).await.ok() {
-            Some(game) => {
-                let screenshots = self
-                    .screenshots_client
-                    .get_by_game_id(game.id, 4)
-                    .await
-                    .unwrap_or_default()
-                    .into_iter()
-                    .map(|x| {
-                        format!(
-                            "https://images.igdb.com/igdb/image/upload/t_original/{}.jpg",
-                            x.image_id
-                        )
-                    })
-                    .collect();
-                let cover = match self.covers_client.get_first_by_id(game.cover).await.ok() {
-                    Some(cover) => format!(
-                        "https://images.igdb.com/igdb/image/upload/t_cover_big/{}.png",
-                        cover.image_id
-                    ),
-                    None => "".to_string(),
-                };
-                let data = IgdbMetaData {
-                    summary: game.summary,
-                    story_line: game.storyline,
-                    screenshots,
-                    cover,
-                };
-                Ok(data)
+        let game = match self
+            .games_client
+            .get_by_id(igdb_id, 1)
+            .await
+            .map_err(igdb_error)?
+            .pop()
+        {
+            Some(game) => game,
+            None => {
+                return Err(ProviderError::NotFound(format!(
+                    "No metadata associated with the igdb_id {}",
+                    igdb_id
+                ))
+                .into())
             }
-            None => Err(anyhow!(
-                "No metadata associated with the igdb_id {}",
-                igdb_id
-            )),
-        }
+        };
+        let mut request = RequestBuilder::new();
+        request
+            .all_fields()
+            .add_where("game", Equality::Equal, game.id.to_string())
+            .limit(4);
+        let screenshots = self
+            .screenshots_client
+            .get(request)
+            .await
+            .map_err(igdb_error)?
+            .into_iter()
+            .map(|x| {
+                format!(
+                    "https://images.igdb.com/igdb/image/upload/t_original/{}.jpg",
+                    x.image_id
+                )
+            })
+            .collect();
+        // a game without cover is not an error
+        let cover = match self
+            .covers_client
+            .get_by_id(game.cover, 1)
+            .await
+            .map_err(igdb_error)?
+            .pop()
+        {
+            Some(cover) => format!(
+                "https://images.igdb.com/igdb/image/upload/t_cover_big/{}.png",
+                cover.image_id
+            ),
+            None => "".to_string(),
+        };
+        Ok(IgdbMetaData {
+            summary: game.summary,
+            story_line: game.storyline,
+            screenshots,
+            cover,
+        })
     }
 
-    pub async fn refresh_token(&mut self) -> anyhow::Result<()> {
-        if self.token.has_expired() {
-            let token = IgdbToken::get(&self.client_id, &self.client_secret).await?;
-            let games_client = IGDBClient::new(&self.client_id, &self.token.value).games();
-            let screenshots_client =
-                IGDBClient::new(&self.client_id, &self.token.value).screenshots();
-            let covers_client = IGDBClient::new(&self.client_id, &self.token.value).covers();
-            self.token = token;
-            self.games_client = games_client;
-            self.screenshots_client = screenshots_client;
-            self.covers_client = covers_client;
-        }
-        Ok(())
+    // A new client with a fresh token, the current one keeps working
+    // for the fetches still using it
+    pub async fn refresh(&self) -> anyhow::Result<Self> {
+        Self::new(self.client_id.clone(), self.client_secret.clone()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::FetchErrorKind;
+
+    #[test]
+    fn test_igdb_error() {
+        let error = std::io::Error::new(std::io::ErrorKind::InvalidData, "not a list");
+        let error = igdb_error(Box::new(error));
+        assert_eq!(
+            FetchErrorKind::from_error(&error),
+            FetchErrorKind::Unavailable
+        );
     }
 }
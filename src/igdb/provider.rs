@@ -0,0 +1,152 @@
+use anyhow::anyhow;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::{
+    sync::{
+        oneshot::{self, Receiver as OneShotReceiver},
+        Mutex,
+    },
+    task::JoinHandle,
+};
+
+use crate::{
+    app::config::IgdbConfig,
+    db::data::GameMetaData,
+    igdb::client::IgdbClient,
+    limiter::RateLimit,
+    provider::{KeyKind, MetadataProvider, ProviderError, ProviderKey},
+};
+
+// delays between two IGDB authentication attempts, in seconds
+const IGDB_RETRY_MIN_DELAY: u64 = 30;
+const IGDB_RETRY_MAX_DELAY: u64 = 3600;
+
+// Obtain an IgdbClient in the background, retrying with an increasing
+// delay so the server can start while Twitch is unreachable.
+fn authenticate_igdb(igdb_config: IgdbConfig) -> (JoinHandle<()>, OneShotReceiver<IgdbClient>) {
+    let (tx, rx) = oneshot::channel::<IgdbClient>();
+    let auth_task = tokio::spawn(async move {
+        let mut delay = IGDB_RETRY_MIN_DELAY;
+        loop {
+            match IgdbClient::new(
+                igdb_config.client_id.clone(),
+                igdb_config.client_secret.clone(),
+            )
+            .await
+            {
+                Ok(igdb_client) => {
+                    tracing::info!("IGDB client ready");
+                    let _ = tx.send(igdb_client);
+                    break;
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Fail to obtain a Igdb client: {e:#}. Trying again in {} seconds",
+                        delay
+                    );
+                    tokio::time::sleep(tokio::time::Duration::from_secs(delay)).await;
+                    delay = (delay * 2).min(IGDB_RETRY_MAX_DELAY);
+                }
+            }
+        }
+    });
+    (auth_task, rx)
+}
+
+#[derive(Default)]
+struct IgdbState {
+    // Replaced when its token expires, the fetches run on a clone
+    // of the Arc so the lock is not held during the requests
+    client: Option<Arc<IgdbClient>>,
+    auth_rx: Option<OneShotReceiver<IgdbClient>>,
+}
+
+pub struct IgdbProvider {
+    state: Mutex<IgdbState>,
+    auth_task: JoinHandle<()>,
+}
+
+impl IgdbProvider {
+    pub fn new(igdb_config: IgdbConfig) -> Self {
+        let (auth_task, auth_rx) = authenticate_igdb(igdb_config);
+        Self {
+            state: Mutex::new(IgdbState {
+                client: None,
+                auth_rx: Some(auth_rx),
+            }),
+            auth_task,
+        }
+    }
+}
+
+// The provider is dropped when the providers are replaced,
+// an authentication still retrying is of no use anymore
+impl Drop for IgdbProvider {
+    fn drop(&mut self) {
+        self.auth_task.abort();
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for IgdbProvider {
+    fn name(&self) -> &'static str {
+        "IGDB"
+    }
+    fn key_kind(&self) -> KeyKind {
+        KeyKind::IgdbId
+    }
+    // 4 requests per second, a fetch asks for the game,
+    // its screenshots and its cover
+    fn rate_limit(&self) -> RateLimit {
+        RateLimit {
+            requests_per_second: 4.0,
+            requests_per_fetch: 3,
+        }
+    }
+    async fn fetch(&self, key: &ProviderKey) -> anyhow::Result<GameMetaData> {
+        let ProviderKey::IgdbId(igdb_id) = key else {
+            return Err(anyhow!("IGDB cannot fetch metadata using a {}", key));
+        };
+        let client = {
+            let mut state = self.state.lock().await;
+            if let Some(rx) = state.auth_rx.as_mut() {
+                if let Ok(client) = rx.try_recv() {
+                    state.client = Some(Arc::new(client));
+                    state.auth_rx = None;
+                }
+            }
+            match state.client.as_ref() {
+                Some(client) if client.token.has_expired() => {
+                    let client = Arc::new(client.refresh().await?);
+                    state.client = Some(client.clone());
+                    client
+                }
+                Some(client) => client.clone(),
+                None => {
+                    return Err(
+                        ProviderError::Unavailable("IGDB is not available yet".into()).into(),
+                    )
+                }
+            }
+        };
+        Ok(client.fetch_metadata(*igdb_id).await?.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_authentication_stops_with_the_provider() {
+        let provider = IgdbProvider::new(IgdbConfig {
+            client_id: "client_id".into(),
+            client_secret: "client_secret".into(),
+        });
+        let auth_task = provider.auth_task.abort_handle();
+        assert!(!auth_task.is_finished());
+        drop(provider);
+        tokio::task::yield_now().await;
+        assert!(auth_task.is_finished());
+    }
+}
@@ -1,10 +1,17 @@
 pub mod app;
+pub mod cache;
 pub mod cmd;
 pub mod db;
 pub mod fetcher;
 pub mod handlers;
 pub mod igdb;
+pub mod limiter;
 pub mod metacritic;
+pub mod provider;
+pub mod reloader;
 pub mod steam;
 pub mod updater;
 pub mod utils;
+
+#[cfg(test)]
+mod test_utils;
@@ -0,0 +1,150 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+use tokio::time::{Duration, Instant};
+
+// Limit documented by a provider, in requests sent to its API
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub requests_per_second: f64,
+    // number of requests a single fetch sends
+    pub requests_per_fetch: u32,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+// Token bucket refilled at the rate of the limit, a fetch
+// takes as many tokens as the requests it sends
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate_limit: RateLimit,
+    capacity: f64,
+    bucket: Mutex<Bucket>,
+    // high priority fetches waiting for tokens
+    high_priority_waiting: AtomicUsize,
+}
+
+// Count a high priority fetch as waiting until it is dropped,
+// even when the fetch is cancelled
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl<'a> Waiting<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self(counter)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl RateLimiter {
+    pub fn new(rate_limit: RateLimit) -> Self {
+        // the bucket must hold at least one fetch
+        let capacity = rate_limit
+            .requests_per_second
+            .max(rate_limit.requests_per_fetch as f64);
+        Self {
+            rate_limit,
+            capacity,
+            bucket: Mutex::new(Bucket {
+                tokens: capacity,
+                last_refill: Instant::now(),
+            }),
+            high_priority_waiting: AtomicUsize::new(0),
+        }
+    }
+    // Wait until the tokens needed for one fetch are available,
+    // low priority fetches leave them to the high priority ones
+    pub async fn acquire(&self, high_priority: bool) {
+        let cost = self.rate_limit.requests_per_fetch as f64;
+        let _waiting = high_priority.then(|| Waiting::new(&self.high_priority_waiting));
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let now = Instant::now();
+                let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * self.rate_limit.requests_per_second)
+                    .min(self.capacity);
+                bucket.last_refill = now;
+                if !high_priority && self.high_priority_waiting.load(Ordering::SeqCst) > 0 {
+                    // checked again once a high priority fetch could be served
+                    cost / self.rate_limit.requests_per_second
+                } else if bucket.tokens >= cost {
+                    bucket.tokens -= cost;
+                    return;
+                } else {
+                    (cost - bucket.tokens) / self.rate_limit.requests_per_second
+                }
+            };
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_burst_is_not_delayed() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_second: 4.0,
+            requests_per_fetch: 1,
+        });
+        let start = Instant::now();
+        for _ in 0..4 {
+            limiter.acquire(false).await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+    #[tokio::test]
+    async fn test_rate_is_enforced() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_second: 20.0,
+            requests_per_fetch: 2,
+        });
+        let start = Instant::now();
+        // the 10 first fetches empty the bucket, the next 2 need 4 more tokens
+        for _ in 0..12 {
+            limiter.acquire(false).await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(190));
+    }
+    #[tokio::test]
+    async fn test_high_priority_is_served_first() {
+        let limiter = Arc::new(RateLimiter::new(RateLimit {
+            requests_per_second: 20.0,
+            requests_per_fetch: 1,
+        }));
+        for _ in 0..20 {
+            limiter.acquire(false).await;
+        }
+        // background fetches are already waiting for the bucket to refill
+        let served = Arc::new(Mutex::new(vec![]));
+        let mut join_set = tokio::task::JoinSet::new();
+        for high_priority in [false, false, false, false, true] {
+            let limiter = limiter.clone();
+            let served = served.clone();
+            join_set.spawn(async move {
+                limiter.acquire(high_priority).await;
+                served.lock().unwrap().push(high_priority);
+            });
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        while join_set.join_next().await.is_some() {}
+        assert_eq!(
+            *served.lock().unwrap(),
+            vec![true, false, false, false, false]
+        );
+    }
+}
@@ -1,10 +1,19 @@
 use anyhow::Context;
-use pledge::pledge_promises;
+use pledge::{pledge, pledge_promises, Promise, ToPromiseString};
 use pobsd_server::{
-    app::config::AppConfig, cmd, db::AppDb, fetcher::MetaDataFetcher, handlers::get_router,
-    updater::GameDbUpdater, utils,
+    app::config::{AppConfig, DatabaseSource},
+    cache::{CacheFlusher, MetaDataCache},
+    cmd,
+    db::AppDb,
+    fetcher::MetaDataFetcher,
+    handlers::get_router,
+    provider::get_providers,
+    reloader::ConfigReloader,
+    updater::GameDbUpdater,
+    utils,
 };
 use std::sync::Arc;
+use tokio::sync::watch;
 use unveil::unveil;
 
 #[tokio::main]
@@ -17,7 +26,8 @@ async fn main() -> anyhow::Result<()> {
 
     // Construct a subscriber that prints formatted traces to stdout
     // use that subscriber as global default
-    tracing::subscriber::set_global_default(utils::get_subscriber())?;
+    let (subscriber, log_handle) = utils::get_subscriber();
+    tracing::subscriber::set_global_default(subscriber)?;
 
     // Get command line arguments
     let cmd_args = cmd::get_args().get_matches();
@@ -28,21 +38,121 @@ async fn main() -> anyhow::Result<()> {
         .or_else(unveil::Error::ignore_platform)
         .unwrap();
     let config = AppConfig::from_init_file(config_file_path)?;
-    pledge_promises![Stdio Inet Rpath Dns].unwrap();
+    if config.log_config.filter.is_some() {
+        utils::set_log_filter(&log_handle, config.log_config.filter.as_deref());
+    }
+    // Kept to tell what changed when the configuration is reloaded
+    let running_config = config.clone();
+    let mut promises = vec![Promise::Stdio, Promise::Inet, Promise::Rpath, Promise::Dns];
 
-    // Load the game database from GitHub
-    let game_database = utils::get_db(utils::DB_URL).await?;
+    // The whole directory is needed to write the cache
+    // through a temporary file
+    let cache_config = config.cache_config;
+    if let Some(ref cache_path) = cache_config.path {
+        let cache_dir = match cache_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => std::path::PathBuf::from("."),
+        };
+        unveil(cache_dir.to_string_lossy().as_ref(), "rwc")
+            .or_else(unveil::Error::ignore_platform)
+            .unwrap();
+        promises.extend([Promise::Wpath, Promise::Cpath]);
+    }
+
+    // Needed to read or pull a local database
+    let database_config = config.database_config;
+    match database_config.source {
+        DatabaseSource::Url(_) => {}
+        DatabaseSource::File(ref path) => {
+            unveil(path.to_string_lossy().as_ref(), "r")
+                .or_else(unveil::Error::ignore_platform)
+                .unwrap();
+        }
+        DatabaseSource::Git { ref repository, .. } => {
+            unveil(repository.to_string_lossy().as_ref(), "rwc")
+                .or_else(unveil::Error::ignore_platform)
+                .unwrap();
+            for (path, permissions) in utils::GIT_UNVEILS {
+                unveil(path, permissions)
+                    .or_else(unveil::Error::ignore_platform)
+                    .unwrap();
+            }
+            // git writes into the repository
+            if cache_config.path.is_none() {
+                promises.extend([Promise::Wpath, Promise::Cpath]);
+            }
+            promises.extend([Promise::Proc, Promise::Exec]);
+        }
+    }
+    pledge(&*promises.to_promise_string(), None).unwrap();
+
+    // Load the metadata cache from the previous run if any
+    let metadata_cache = match cache_config.path {
+        Some(ref cache_path) => MetaDataCache::load(cache_path).unwrap_or_else(|e| {
+            tracing::warn!("Starting with an empty metadata cache: {e:#}");
+            MetaDataCache::default()
+        }),
+        None => MetaDataCache::default(),
+    };
+
+    // Load the game database
+    tracing::info!("Loading the game database from {}", database_config.source);
+    let mut db_state = utils::DbState::default();
+    let game_database = utils::get_db(&database_config.source, &mut db_state)
+        .await?
+        .context("The game database was not loaded")?;
+
+    // Tell the background tasks to stop when the server does
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
 
     // Launch the medatata fetcher
-    let igdb_config = config.igdb_config;
     tracing::info!("Launching fetcher");
-    let fetcher = MetaDataFetcher::init(igdb_config.client_id, igdb_config.client_secret).await;
+    let metadata_config = config.metadata_config;
+    let providers = get_providers(config.igdb_config, &metadata_config);
+    let fetcher = MetaDataFetcher::init(providers, shutdown_rx.clone());
 
     // Launch the Db and get the connector back
-    let db_con = Arc::new(AppDb::new(game_database, fetcher.high_priority.clone()).launch());
+    let db_con = Arc::new(
+        AppDb::new(
+            game_database,
+            metadata_cache,
+            cache_config.max_age,
+            metadata_config,
+            config.pagination_config,
+            fetcher.high_priority.clone(),
+            fetcher.low_priority.clone(),
+        )
+        .launch(),
+    );
+
+    // Launch the regular flush of the metadata cache
+    let cache_flusher = cache_config.path.clone().map(|cache_path| {
+        CacheFlusher::init(db_con.clone(), cache_path, cache_config.flush_period)
+    });
+    let cache_flusher_handle = cache_flusher
+        .as_ref()
+        .map(|cache_flusher| cache_flusher.launch(shutdown_rx.clone()));
 
     // Launch the regular update of game data and metadata
-    GameDbUpdater::init(db_con.clone(), fetcher.low_priority.clone()).launch();
+    let (database_config_tx, database_config_rx) = watch::channel(database_config);
+    let updater_handle = GameDbUpdater::init(
+        db_con.clone(),
+        fetcher.low_priority.clone(),
+        database_config_rx,
+        db_state,
+    )
+    .launch(shutdown_rx.clone());
+
+    // Reload the configuration on SIGHUP
+    ConfigReloader::init(
+        config_file_path.clone(),
+        running_config,
+        db_con.clone(),
+        fetcher.providers.clone(),
+        database_config_tx,
+        log_handle,
+    )
+    .launch(shutdown_rx);
 
     // Launch the router
     let router = get_router(db_con);
@@ -53,11 +163,35 @@ async fn main() -> anyhow::Result<()> {
         .unwrap();
 
     tracing::info!("Listening to incoming requests");
-    axum::serve(listener, router).await.unwrap();
+    // New connections are refused once the signal is received,
+    // serve returns when the requests in progress are answered
+    axum::serve(listener, router)
+        .with_graceful_shutdown(async move {
+            utils::shutdown_signal().await;
+            tracing::info!("Shutting down");
+            let _ = shutdown_tx.send(true);
+        })
+        .await
+        .unwrap();
+
+    // Wait for the background tasks before the last flush
+    let _ = updater_handle.await;
+    match fetcher.join_handler.await {
+        Ok(Err(e)) => tracing::warn!("Metadata fetcher failed: {e:#}"),
+        Err(e) => tracing::warn!("Metadata fetcher panicked: {e}"),
+        Ok(Ok(())) => {}
+    }
+    if let Some(cache_flusher_handle) = cache_flusher_handle {
+        let _ = cache_flusher_handle.await;
+    }
 
-    // Join the remaining task
-    let _ = fetcher.join_handler.await.context("Fail to join tasks")?;
-    tracing::info!("Shutting down fetcher");
+    // Keep what has been fetched for the next run
+    if let Some(cache_flusher) = cache_flusher {
+        match cache_flusher.flush().await {
+            Ok(_) => tracing::info!("Metadata cache flushed"),
+            Err(e) => tracing::warn!("Failed to flush metadata cache: {e:#}"),
+        }
+    }
 
     Ok(())
 }
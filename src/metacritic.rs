@@ -1,8 +1,13 @@
 use anyhow::anyhow;
+use async_trait::async_trait;
 use scraper::Selector;
 use serde::Deserialize;
 
-use crate::db::data::{GameMetaData, Metacritic};
+use crate::{
+    db::data::{GameMetaData, Metacritic},
+    limiter::RateLimit,
+    provider::{KeyKind, MetadataProvider, ProviderError, ProviderKey},
+};
 
 #[derive(Deserialize, Debug, Default)]
 #[serde(rename_all = "camelCase")]
@@ -36,6 +41,7 @@ impl From<MetacriticMetaData> for GameMetaData {
             cover: None,
             metacritic,
             screenshots: vec![],
+            ..Default::default()
         }
     }
 }
@@ -72,9 +78,32 @@ impl MetacriticClient {
                         return Ok(data);
                     }
                 }
-                Err(anyhow!("Failed to find metadata"))
+                Err(ProviderError::NotFound("Failed to find metadata".into()).into())
             }
             Err(_) => Err(anyhow!("Failed to find metadata")),
         }
     }
 }
+
+#[async_trait]
+impl MetadataProvider for MetacriticClient {
+    fn name(&self) -> &'static str {
+        "Metacritic"
+    }
+    fn key_kind(&self) -> KeyKind {
+        KeyKind::GameName
+    }
+    // no documented limit, the website is scraped so stay polite
+    fn rate_limit(&self) -> RateLimit {
+        RateLimit {
+            requests_per_second: 1.0,
+            requests_per_fetch: 1,
+        }
+    }
+    async fn fetch(&self, key: &ProviderKey) -> anyhow::Result<GameMetaData> {
+        match key {
+            ProviderKey::GameName(game_name) => Ok(self.fetch_metadata(game_name).await?.into()),
+            _ => Err(anyhow!("Metacritic cannot fetch metadata using a {}", key)),
+        }
+    }
+}
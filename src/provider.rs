@@ -0,0 +1,313 @@
+use async_trait::async_trait;
+use libpobsd::Game;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, sync::Arc};
+
+use crate::{
+    app::config::{IgdbConfig, MetadataConfig, ProviderKind},
+    db::data::GameMetaData,
+    igdb::provider::IgdbProvider,
+    limiter::RateLimit,
+    metacritic::MetacriticClient,
+    steam::SteamClient,
+};
+
+// The kind of key a provider uses to look a game up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyKind {
+    IgdbId,
+    SteamId,
+    GameName,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProviderKey {
+    IgdbId(usize),
+    SteamId(usize),
+    GameName(String),
+}
+
+impl Display for ProviderKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProviderKey::IgdbId(igdb_id) => write!(f, "igdb id {}", igdb_id),
+            ProviderKey::SteamId(steam_id) => write!(f, "steam id {}", steam_id),
+            ProviderKey::GameName(name) => write!(f, "game name {}", name),
+        }
+    }
+}
+
+// All the keys that can be derived from a game
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataKeys {
+    pub igdb_id: Option<usize>,
+    pub steam_id: Option<usize>,
+    pub game_name: String,
+}
+
+impl MetadataKeys {
+    pub fn get(&self, key_kind: KeyKind) -> Option<ProviderKey> {
+        match key_kind {
+            KeyKind::IgdbId => self.igdb_id.map(ProviderKey::IgdbId),
+            KeyKind::SteamId => self.steam_id.map(ProviderKey::SteamId),
+            KeyKind::GameName => Some(ProviderKey::GameName(self.game_name.clone())),
+        }
+    }
+}
+
+impl From<&Game> for MetadataKeys {
+    fn from(game: &Game) -> Self {
+        Self {
+            igdb_id: game.igdb_id,
+            steam_id: game.get_steam_id(),
+            game_name: game.name.clone(),
+        }
+    }
+}
+
+// Errors a provider can return to tell why a lookup failed,
+// other errors are classified from their source
+#[derive(Debug)]
+pub enum ProviderError {
+    NotFound(String),
+    Unavailable(String),
+}
+
+impl Display for ProviderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProviderError::NotFound(message) => write!(f, "{}", message),
+            ProviderError::Unavailable(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FetchErrorKind {
+    // the provider does not know the game
+    NotFound,
+    // the provider refused or could not serve the request
+    Unavailable,
+    // the provider could not be reached
+    Network,
+    // the provider answered something we could not use
+    Invalid,
+}
+
+impl FetchErrorKind {
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        if let Some(error) = error.downcast_ref::<ProviderError>() {
+            return match error {
+                ProviderError::NotFound(_) => FetchErrorKind::NotFound,
+                ProviderError::Unavailable(_) => FetchErrorKind::Unavailable,
+            };
+        }
+        if let Some(error) = error.downcast_ref::<reqwest::Error>() {
+            if error.is_timeout() || error.is_connect() {
+                return FetchErrorKind::Network;
+            }
+            return match error.status() {
+                Some(status) if status == reqwest::StatusCode::NOT_FOUND => {
+                    FetchErrorKind::NotFound
+                }
+                Some(status)
+                    if status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                        || status.is_server_error() =>
+                {
+                    FetchErrorKind::Unavailable
+                }
+                Some(_) => FetchErrorKind::Invalid,
+                None => FetchErrorKind::Network,
+            };
+        }
+        FetchErrorKind::Invalid
+    }
+    // the provider may answer if asked again a bit later
+    pub fn is_transient(&self) -> bool {
+        matches!(self, FetchErrorKind::Unavailable | FetchErrorKind::Network)
+    }
+    // first delay before trying again, in seconds
+    pub fn retry_delay(&self) -> u64 {
+        match self {
+            FetchErrorKind::NotFound => 86400,
+            FetchErrorKind::Unavailable => 900,
+            FetchErrorKind::Network => 300,
+            FetchErrorKind::Invalid => 3600,
+        }
+    }
+}
+
+impl Display for FetchErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchErrorKind::NotFound => write!(f, "not found"),
+            FetchErrorKind::Unavailable => write!(f, "unavailable"),
+            FetchErrorKind::Network => write!(f, "network error"),
+            FetchErrorKind::Invalid => write!(f, "invalid answer"),
+        }
+    }
+}
+
+// A provider that failed a lookup other providers answered
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderFailure {
+    pub provider: String,
+    pub kind: FetchErrorKind,
+}
+
+// The name of a provider and what it answered
+pub type ProviderAnswer = (&'static str, Result<GameMetaData, FetchErrorKind>);
+
+// What each provider queried for a game answered,
+// in their order of priority
+#[derive(Debug, Clone, Default)]
+pub struct FetchedMetaData {
+    pub answers: Vec<ProviderAnswer>,
+}
+
+impl FetchedMetaData {
+    // The metadata of the providers merged following their priority, the
+    // fields a provider that failed gave to the previous lookup are kept
+    pub fn metadata(&self, previous: Option<&GameMetaData>) -> GameMetaData {
+        self.answers
+            .iter()
+            .filter_map(|(provider, result)| match result {
+                Ok(metadata) => Some(metadata.clone()),
+                Err(_) => previous.map(|previous| previous.fields_from(provider)),
+            })
+            .fold(GameMetaData::default(), GameMetaData::merge)
+    }
+    pub fn failures(&self) -> Vec<ProviderFailure> {
+        self.answers
+            .iter()
+            .filter_map(|(provider, result)| match result {
+                Ok(_) => None,
+                Err(kind) => Some(ProviderFailure {
+                    provider: provider.to_string(),
+                    kind: *kind,
+                }),
+            })
+            .collect()
+    }
+}
+
+// Err when every provider queried for a game failed
+pub type FetchResult = Result<FetchedMetaData, FetchErrorKind>;
+
+#[async_trait]
+pub trait MetadataProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn key_kind(&self) -> KeyKind;
+    fn rate_limit(&self) -> RateLimit;
+    async fn fetch(&self, key: &ProviderKey) -> anyhow::Result<GameMetaData>;
+}
+
+// Returns the enabled providers, in the order of priority set in the config
+pub fn get_providers(
+    igdb_config: Option<IgdbConfig>,
+    metadata_config: &MetadataConfig,
+) -> Vec<Arc<dyn MetadataProvider>> {
+    let mut providers: Vec<Arc<dyn MetadataProvider>> = vec![];
+    for provider in &metadata_config.providers {
+        match provider {
+            ProviderKind::Igdb => match igdb_config {
+                Some(ref igdb_config) => {
+                    providers.push(Arc::new(IgdbProvider::new(igdb_config.clone())))
+                }
+                None => tracing::warn!("IGDB is enabled without credentials, ignoring it"),
+            },
+            ProviderKind::Steam => providers.push(Arc::new(SteamClient::new())),
+            ProviderKind::Metacritic => providers.push(Arc::new(MetacriticClient::new())),
+        }
+    }
+    providers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libpobsd::{StoreLink, StoreLinks};
+
+    #[test]
+    fn test_metadata_keys_from_game() {
+        let mut game = Game::new();
+        game.name = "Game".into();
+        game.igdb_id = Some(12);
+        game.stores = Some(StoreLinks::new(vec![
+            StoreLink::from("https://store.steampowered.com/app/1869200/The_Game/"),
+            StoreLink::from("https://www.gog.com/game/the_game"),
+        ]));
+        let keys = MetadataKeys::from(&game);
+        assert_eq!(keys.get(KeyKind::IgdbId), Some(ProviderKey::IgdbId(12)));
+        assert_eq!(
+            keys.get(KeyKind::SteamId),
+            Some(ProviderKey::SteamId(1869200))
+        );
+        assert_eq!(
+            keys.get(KeyKind::GameName),
+            Some(ProviderKey::GameName("Game".into()))
+        );
+        // no provider uses the GOG link, editing it changes no key
+        game.stores = Some(StoreLinks::new(vec![StoreLink::from(
+            "https://store.steampowered.com/app/1869200/The_Game/",
+        )]));
+        assert_eq!(MetadataKeys::from(&game), keys);
+    }
+    #[test]
+    fn test_fetch_error_kind_from_error() {
+        let error = anyhow::Error::new(ProviderError::NotFound("no game".into()));
+        assert_eq!(FetchErrorKind::from_error(&error), FetchErrorKind::NotFound);
+        let error = anyhow::Error::new(ProviderError::Unavailable("not ready".into()));
+        assert_eq!(
+            FetchErrorKind::from_error(&error),
+            FetchErrorKind::Unavailable
+        );
+        let error = anyhow::anyhow!("something else");
+        assert_eq!(FetchErrorKind::from_error(&error), FetchErrorKind::Invalid);
+    }
+    #[test]
+    fn test_metadata_keys_without_store() {
+        let keys = MetadataKeys::from(&Game::new());
+        assert_eq!(keys.get(KeyKind::IgdbId), None);
+        assert_eq!(keys.get(KeyKind::SteamId), None);
+    }
+    #[test]
+    fn test_fetched_metadata_keeps_fields_of_failed_providers() {
+        let previous = GameMetaData {
+            summary: Some("old igdb".into()),
+            cover: Some("old.png".into()),
+            ..Default::default()
+        }
+        .with_source("IGDB")
+        .merge(
+            GameMetaData {
+                summary: Some("old steam".into()),
+                story_line: Some("old steam".into()),
+                ..Default::default()
+            }
+            .with_source("Steam"),
+        );
+        let fetched = FetchedMetaData {
+            answers: vec![
+                ("IGDB", Err(FetchErrorKind::Unavailable)),
+                (
+                    "Steam",
+                    Ok(GameMetaData {
+                        summary: Some("new steam".into()),
+                        ..Default::default()
+                    }
+                    .with_source("Steam")),
+                ),
+            ],
+        };
+        let metadata = fetched.metadata(Some(&previous));
+        // IGDB still comes first
+        assert_eq!(metadata.summary, Some("old igdb".into()));
+        assert_eq!(metadata.cover, Some("old.png".into()));
+        // Steam answered without story line
+        assert_eq!(metadata.story_line, None);
+        assert_eq!(fetched.metadata(None).summary, Some("new steam".into()));
+    }
+}
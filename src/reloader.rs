@@ -0,0 +1,178 @@
+use std::sync::Arc;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch::{Receiver as WatchReceiver, Sender as WatchSender},
+    task::JoinHandle,
+};
+
+use crate::{
+    app::config::{AppConfig, DatabaseConfig, DatabaseSource},
+    db::connector::DbCon,
+    fetcher::ProviderSet,
+    provider::get_providers,
+    utils::{set_log_filter, LogHandle},
+};
+
+// Settings only read at startup, a new value is
+// kept aside until the server is restarted
+fn keep_restart_settings(running: &AppConfig, config: &mut AppConfig) -> Vec<&'static str> {
+    let mut ignored = vec![];
+    if config.server_config != running.server_config {
+        ignored.push("listen address");
+        config.server_config = running.server_config.clone();
+    }
+    if config.cache_config.path != running.cache_config.path {
+        ignored.push("cache path");
+        config.cache_config.path = running.cache_config.path.clone();
+    }
+    if config.cache_config.flush_period != running.cache_config.flush_period {
+        ignored.push("cache flush period");
+        config.cache_config.flush_period = running.cache_config.flush_period;
+    }
+    // the paths and promises a source needs are set by unveil and
+    // pledge at startup, only the url of a remote database can change
+    let source = &config.database_config.source;
+    let running_source = &running.database_config.source;
+    let same_kind = matches!(
+        (source, running_source),
+        (DatabaseSource::Url(_), DatabaseSource::Url(_))
+    );
+    if !same_kind && source != running_source {
+        ignored.push("database source");
+        config.database_config.source = running_source.clone();
+    }
+    ignored
+}
+
+// Reload the configuration file on SIGHUP and apply
+// what can be changed while the server runs
+pub struct ConfigReloader {
+    config_path: String,
+    config: AppConfig,
+    db_con: Arc<DbCon>,
+    providers: ProviderSet,
+    database_config: WatchSender<DatabaseConfig>,
+    log_handle: LogHandle,
+}
+
+impl ConfigReloader {
+    pub fn init(
+        config_path: String,
+        config: AppConfig,
+        db_con: Arc<DbCon>,
+        providers: ProviderSet,
+        database_config: WatchSender<DatabaseConfig>,
+        log_handle: LogHandle,
+    ) -> Self {
+        Self {
+            config_path,
+            config,
+            db_con,
+            providers,
+            database_config,
+            log_handle,
+        }
+    }
+    async fn apply(&mut self, mut config: AppConfig) {
+        for setting in keep_restart_settings(&self.config, &mut config) {
+            tracing::warn!("The {} cannot be changed without a restart", setting);
+        }
+        if config.log_config != self.config.log_config {
+            set_log_filter(&self.log_handle, config.log_config.filter.as_deref());
+        }
+        // the providers are only created again when needed since
+        // IGDB authenticates again and the rate limits start over
+        if config.igdb_config != self.config.igdb_config
+            || config.metadata_config.providers != self.config.metadata_config.providers
+        {
+            self.providers.replace(get_providers(
+                config.igdb_config.clone(),
+                &config.metadata_config,
+            ));
+        }
+        if config.metadata_config != self.config.metadata_config
+            || config.cache_config.max_age != self.config.cache_config.max_age
+        {
+            self.db_con
+                .update_metadata_config(config.metadata_config.clone(), config.cache_config.max_age)
+                .await;
+        }
+        if config.pagination_config != self.config.pagination_config {
+            self.db_con
+                .update_pagination_config(config.pagination_config.clone())
+                .await;
+        }
+        // sent even if it did not change so the database is updated right away
+        self.database_config
+            .send_replace(config.database_config.clone());
+        self.config = config;
+        tracing::info!("Configuration reloaded");
+    }
+    pub fn launch(mut self, mut shutdown: WatchReceiver<bool>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut sighup = match signal(SignalKind::hangup()) {
+                Ok(sighup) => sighup,
+                Err(e) => {
+                    tracing::warn!("Failed to handle SIGHUP, reloading is disabled: {e}");
+                    return;
+                }
+            };
+            loop {
+                tokio::select! {
+                    Some(_) = sighup.recv() => {}
+                    _ = shutdown.changed() => break,
+                }
+                tracing::info!("Received SIGHUP, reloading {}", self.config_path);
+                match AppConfig::from_init_file(&self.config_path) {
+                    Ok(config) => self.apply(config).await,
+                    Err(e) => tracing::warn!(
+                        "Failed to reload {}: {e:#}. Keeping the current configuration",
+                        self.config_path
+                    ),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_settings_are_kept() {
+        let running = AppConfig::default();
+        let mut config =
+            AppConfig::from_init_file("tests/config_file/pobsd-server-cache.conf").unwrap();
+        let ignored = keep_restart_settings(&running, &mut config);
+        assert_eq!(
+            ignored,
+            vec!["listen address", "cache path", "cache flush period"]
+        );
+        assert_eq!(config.server_config, running.server_config);
+        assert_eq!(config.cache_config.path, running.cache_config.path);
+        // the other settings are applied at runtime
+        assert_eq!(config.cache_config.max_age, 3600);
+        assert!(config.igdb_config.is_some());
+    }
+    #[test]
+    fn test_database_source_kind_is_kept() {
+        let running = AppConfig::default();
+        let mut config =
+            AppConfig::from_init_file("tests/config_file/pobsd-server-db-file.conf").unwrap();
+        let ignored = keep_restart_settings(&running, &mut config);
+        assert!(ignored.contains(&"database source"));
+        assert_eq!(
+            config.database_config.source,
+            running.database_config.source
+        );
+        // the update period is applied at runtime
+        assert_eq!(config.database_config.update_period, 60);
+        // and so is another url
+        let mut config = AppConfig::default();
+        let url = DatabaseSource::Url("https://example.org/openbsd-games.db".into());
+        config.database_config.source = url.clone();
+        assert!(keep_restart_settings(&running, &mut config).is_empty());
+        assert_eq!(config.database_config.source, url);
+    }
+}
@@ -1,9 +1,15 @@
 use std::collections::HashMap;
 
+use anyhow::anyhow;
+use async_trait::async_trait;
 use reqwest::StatusCode;
 use serde::Deserialize;
 
-use crate::db::data::{GameMetaData, Metacritic};
+use crate::{
+    db::data::{GameMetaData, Metacritic},
+    limiter::RateLimit,
+    provider::{KeyKind, MetadataProvider, ProviderKey},
+};
 
 #[derive(Deserialize, Debug, Clone, Default)]
 pub struct SteamMetacritic {
@@ -38,15 +44,16 @@ pub struct SteamMetaData {
 impl From<SteamMetaData> for GameMetaData {
     fn from(value: SteamMetaData) -> Self {
         Self {
-            summary: Some(value.short_description),
+            summary: Some(value.short_description).filter(|x| !x.is_empty()),
             story_line: None,
             screenshots: value
                 .screenshots
                 .into_iter()
                 .map(|x| x.path_thumbnail)
                 .collect(),
-            cover: Some(value.cover),
+            cover: Some(value.cover).filter(|x| !x.is_empty()),
             metacritic: value.metacritic.map(|x| x.into()),
+            ..Default::default()
         }
     }
 }
@@ -110,3 +117,27 @@ impl SteamClient {
         Ok(metadata)
     }
 }
+
+#[async_trait]
+impl MetadataProvider for SteamClient {
+    fn name(&self) -> &'static str {
+        "Steam"
+    }
+    fn key_kind(&self) -> KeyKind {
+        KeyKind::SteamId
+    }
+    // the store API allows 200 requests every 5 minutes,
+    // the cover is checked on the CDN which is not limited
+    fn rate_limit(&self) -> RateLimit {
+        RateLimit {
+            requests_per_second: 200.0 / 300.0,
+            requests_per_fetch: 1,
+        }
+    }
+    async fn fetch(&self, key: &ProviderKey) -> anyhow::Result<GameMetaData> {
+        match key {
+            ProviderKey::SteamId(steam_id) => Ok(self.fetch_metadata(*steam_id).await?.into()),
+            _ => Err(anyhow!("Steam cannot fetch metadata using a {}", key)),
+        }
+    }
+}
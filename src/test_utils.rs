@@ -0,0 +1,30 @@
+use libpobsd::{Game, GameDataBase};
+
+use crate::utils::parse_db;
+
+// the game database the tests run against
+pub const TEST_DB_PATH: &str = "tests/db/openbsd-games.db";
+
+pub fn test_db_content() -> String {
+    std::fs::read_to_string(TEST_DB_PATH).unwrap()
+}
+
+pub fn test_db() -> GameDataBase {
+    parse_db(&test_db_content())
+}
+
+// The games of the test database in the order of the game list
+pub fn test_games() -> Vec<Game> {
+    test_db().get_all_games().into_iter().cloned().collect()
+}
+
+pub fn game_id(db: &GameDataBase, name: &str) -> u32 {
+    match db
+        .get_all_games()
+        .into_iter()
+        .find(|game| game.name.eq(name))
+    {
+        Some(game) => game.uid,
+        None => panic!("{} is not in the test database", name),
+    }
+}
@@ -1,68 +1,145 @@
 use libpobsd::Game;
 use std::{collections::HashSet, sync::Arc};
-use tokio::sync::{mpsc::Sender as MpscSender, oneshot};
+use tokio::{
+    sync::{mpsc::Sender as MpscSender, oneshot, watch::Receiver as WatchReceiver},
+    task::JoinHandle,
+};
 
 use crate::{
-    db::{connector::DbCon, data::GameMetaData, responses::AppDbResponse},
+    app::config::{DatabaseConfig, DatabaseSource},
+    db::{connector::DbCon, responses::AppDbResponse},
     fetcher::FetcherMessage,
-    utils::{get_db, DB_URL},
+    provider::{FetchResult, MetadataKeys},
+    utils::{get_db, get_db_modified, DbState, WATCH_PERIOD},
 };
 
 pub struct GameDbUpdater {
     db_con: Arc<DbCon>,
     lptx: MpscSender<FetcherMessage>,
+    // a new config triggers an update right away
+    database_config: WatchReceiver<DatabaseConfig>,
+    // state of the database loaded at startup
+    db_state: DbState,
 }
 
 impl GameDbUpdater {
-    pub fn init(db_con: Arc<DbCon>, lptx: MpscSender<FetcherMessage>) -> Self {
-        Self { db_con, lptx }
+    pub fn init(
+        db_con: Arc<DbCon>,
+        lptx: MpscSender<FetcherMessage>,
+        database_config: WatchReceiver<DatabaseConfig>,
+        db_state: DbState,
+    ) -> Self {
+        Self {
+            db_con,
+            lptx,
+            database_config,
+            db_state,
+        }
     }
-    pub fn launch(&self) {
+    // The updater stops at the next check once the shutdown is requested
+    pub fn launch(&self, mut shutdown: WatchReceiver<bool>) -> JoinHandle<()> {
         tokio::spawn({
             let db_con = self.db_con.clone();
             let lptx = self.lptx.clone();
+            let mut database_config = self.database_config.clone();
+            let mut db_state = self.db_state.clone();
             async move {
                 tracing::debug!("Launching game database updater");
-                let game_ids = match db_con.get_game_ids().await {
+                db_con.update_db_state(db_state.clone()).await;
+                let mut game_ids = match db_con.get_game_ids().await {
                     AppDbResponse::GameIds(game_ids) => game_ids,
                     _ => unreachable!("GameIds is the only possible variant"),
                 };
-                let to_fetch = match db_con.get_all_games().await {
-                    AppDbResponse::AllGames(games) => get_game_ids(games),
+                let fresh_ids = match db_con.get_fresh_metadata_ids().await {
+                    AppDbResponse::GameIds(fresh_ids) => fresh_ids,
+                    _ => HashSet::new(),
+                };
+                // games with a fresh entry in the metadata cache are skipped
+                let to_fetch: Vec<u32> = match db_con.get_all_games().await {
+                    AppDbResponse::AllGames(games) => get_game_ids(games)
+                        .into_iter()
+                        .filter(|game_id| !fresh_ids.contains(game_id))
+                        .collect(),
                     _ => vec![],
                 };
                 background_fetching(db_con.clone(), lptx.clone(), to_fetch);
-                tokio::time::sleep(tokio::time::Duration::from_secs(500)).await;
+                let DatabaseConfig {
+                    mut source,
+                    mut update_period,
+                } = database_config.borrow_and_update().clone();
+                let mut last_modified = get_db_modified(&source);
                 loop {
-                    match get_db(DB_URL).await.ok() {
-                        Some(db) => {
+                    // a local file is watched for changes instead of
+                    // being reloaded at each update period
+                    let period = match source {
+                        DatabaseSource::File(_) => WATCH_PERIOD,
+                        _ => update_period,
+                    };
+                    let reloaded = tokio::select! {
+                        _ = tokio::time::sleep(tokio::time::Duration::from_secs(period)) => false,
+                        _ = shutdown.changed() => break,
+                        Ok(_) = database_config.changed() => true,
+                    };
+                    if reloaded {
+                        DatabaseConfig {
+                            source,
+                            update_period,
+                        } = database_config.borrow_and_update().clone();
+                        tracing::info!("Reloading the game database from {}", source);
+                        // the database is loaded even if it did not change
+                        db_state.forget_version();
+                        last_modified = get_db_modified(&source);
+                    } else if let DatabaseSource::File(_) = source {
+                        let modified = get_db_modified(&source);
+                        if modified == last_modified {
+                            continue;
+                        }
+                        tracing::info!("{} has been modified, reloading it", source);
+                        last_modified = modified;
+                    }
+                    let result = tokio::select! {
+                        result = get_db(&source, &mut db_state) => result,
+                        _ = shutdown.changed() => break,
+                    };
+                    match result {
+                        Ok(Some(db)) => {
                             let new_game_ids: HashSet<u32> =
                                 db.get_all_games().into_iter().map(|g| g.uid).collect();
-                            if let AppDbResponse::Done = db_con.update_db(db).await {
+                            if let AppDbResponse::GameIds(invalidated) = db_con.update_db(db).await
+                            {
                                 tracing::debug!("The game database has been updated.");
-                                tracing::debug!("Fetching metadata for new games.");
+                                tracing::debug!("Fetching metadata for new and changed games.");
                                 // return the game_id for game that are present in the new
                                 // database but not the old one
-                                let diff: Vec<u32> =
+                                let mut to_fetch: Vec<u32> =
                                     new_game_ids.difference(&game_ids).copied().collect();
-                                background_fetching(db_con.clone(), lptx.clone(), diff);
+                                // and for games whose metadata were invalidated
+                                to_fetch.extend(invalidated);
+                                background_fetching(db_con.clone(), lptx.clone(), to_fetch);
+                                game_ids = new_game_ids;
                             } else {
                                 tracing::debug!(
-                                "Failed to update the game database. Trying again in 500 seconds"
-                            );
+                                    "Failed to update the game database. Trying again in {} seconds",
+                                    period
+                                );
                             }
                         }
-                        None => tracing::debug!(
-                            "Failed to update the game database. Trying again in 500 seconds"
+                        Ok(None) => tracing::debug!("The game database did not change."),
+                        Err(e) => tracing::debug!(
+                            "Failed to update the game database: {e:#}. Trying again in {} seconds",
+                            period
                         ),
                     }
-                    tokio::time::sleep(tokio::time::Duration::from_secs(500)).await;
+                    db_con.update_db_state(db_state.clone()).await;
                 }
+                tracing::info!("Game database updater stopped");
             }
-        });
+        })
     }
 }
 
+// The fetcher paces the requests sent to each provider, so messages are
+// queued as fast as it accepts them and several are in flight at once
 pub fn background_fetching(
     db_con: Arc<DbCon>,
     lptx: MpscSender<FetcherMessage>,
@@ -71,26 +148,31 @@ pub fn background_fetching(
     tokio::spawn({
         async move {
             for game_id in to_fetch {
-                tokio::time::sleep(tokio::time::Duration::from_millis(350)).await;
                 if let AppDbResponse::Game(game) = db_con.get_game(game_id).await {
-                    let (tx, rx) = oneshot::channel::<GameMetaData>();
+                    let keys = MetadataKeys::from(&game);
+                    let (tx, rx) = oneshot::channel::<FetchResult>();
                     let msg = FetcherMessage {
-                        igdb_id: game.igdb_id,
-                        game_name: game.name.clone(),
+                        keys: keys.clone(),
                         sender: tx,
-                        steam_id: game.get_steam_id(),
                     };
-                    match lptx.send(msg).await {
-                        Ok(_) => {
-                            if let Ok(metadata) = rx.await {
-                                db_con.insert_metadata(game_id, metadata).await;
+                    // the fetcher only stops with the server
+                    if lptx.send(msg).await.is_err() {
+                        tracing::debug!("Fetcher stopped, giving up the background fetch");
+                        break;
+                    }
+                    let db_con = db_con.clone();
+                    tokio::spawn(async move {
+                        match rx.await {
+                            Ok(Ok(metadata)) => {
+                                db_con.insert_metadata(game_id, keys, metadata).await;
                                 tracing::debug!("Background fetch for {}", game_id)
                             }
+                            Ok(Err(kind)) => {
+                                db_con.record_fetch_failure(game_id, keys, kind).await;
+                            }
+                            Err(_) => {}
                         }
-                        Err(_) => {
-                            tracing::debug!("Error while doing background fetch for {:?}", game_id)
-                        }
-                    }
+                    });
                 }
             }
         }
@@ -98,7 +180,7 @@ pub fn background_fetching(
 }
 
 pub fn get_game_ids(mut games: Vec<Game>) -> Vec<u32> {
-    games.sort_by(|a, b| a.added.cmp(&b.added));
+    games.sort_by_key(|a| a.added);
     let mut game_ids: Vec<u32> = games.into_iter().map(|x| x.uid).collect();
     game_ids.reverse();
     game_ids
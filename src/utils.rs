@@ -1,28 +1,227 @@
-use anyhow::Context;
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
 use libpobsd::{GameDataBase, GameFilter, Parser, ParserResult};
+use reqwest::{header, StatusCode};
 use serde::{Deserialize, Serialize};
-use std::ops::{Deref, DerefMut};
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::{Deref, DerefMut},
+    path::Path,
+    time::SystemTime,
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch::Receiver as WatchReceiver,
+};
 use tracing::{level_filters::LevelFilter, Level, Subscriber};
-use tracing_subscriber::{filter::Targets, fmt, prelude::*, EnvFilter};
+use tracing_subscriber::{filter::Targets, fmt, prelude::*, reload, EnvFilter, Registry};
+
+use crate::app::config::DatabaseSource;
 
 pub const DB_URL: &str =
     "https://raw.githubusercontent.com/playonbsd/OpenBSD-Games-Database/main/openbsd-games.db";
 pub const UPDATE_PERIOD: u64 = 500;
+// how often a local database file is checked for changes
+pub const WATCH_PERIOD: u64 = 5;
+pub const GIT_PATH: &str = "/usr/local/bin/git";
+// What git needs once unveiled: the dynamic loader, the shared
+// libraries, its helpers such as git-remote-https and /dev/null
+pub const GIT_UNVEILS: [(&str, &str); 6] = [
+    (GIT_PATH, "rx"),
+    ("/usr/libexec/ld.so", "rx"),
+    ("/usr/lib", "r"),
+    ("/usr/local/lib", "r"),
+    ("/usr/local/libexec/git", "rx"),
+    ("/dev/null", "rw"),
+];
+
+// What is known about the last version of the database,
+// used to skip the download and the parsing when it did not change
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DbState {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    // hash of the content of the database
+    pub hash: Option<String>,
+    // last time a new version of the database was fetched
+    pub last_fetch: Option<DateTime<Utc>>,
+    // last time the source was checked for a new version
+    pub last_check: Option<DateTime<Utc>>,
+    // lines of the last version rejected by the parser
+    pub parse_errors: Vec<ParseError>,
+}
 
-pub async fn get_db(db_url: &str) -> anyhow::Result<GameDataBase> {
-    let req = reqwest::get(db_url)
+impl DbState {
+    // The next get_db loads the database even if it did not change
+    pub fn forget_version(&mut self) {
+        self.etag = None;
+        self.last_modified = None;
+        self.hash = None;
+    }
+}
+
+// A line of the database the parser could not make sense of
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ParseError {
+    pub line: usize,
+    pub content: String,
+}
+
+// Returns None when the database did not change since the last call
+pub async fn get_db(
+    source: &DatabaseSource,
+    db_state: &mut DbState,
+) -> anyhow::Result<Option<GameDataBase>> {
+    let (content, validators) = match source {
+        DatabaseSource::Url(db_url) => match get_db_content_from_url(db_url, db_state).await? {
+            Some((content, validators)) => (Some(content), Some(validators)),
+            None => (None, None),
+        },
+        DatabaseSource::File(path) => (Some(get_db_content_from_file(path).await?), None),
+        DatabaseSource::Git { repository, file } => {
+            pull_git_repository(repository).await?;
+            let content = get_db_content_from_file(&repository.join(file)).await?;
+            (Some(content), None)
+        }
+    };
+    db_state.last_check = Some(Utc::now());
+    let Some(content) = content else {
+        tracing::debug!("{} was not modified", source);
+        return Ok(None);
+    };
+    let hash = get_hash(&content);
+    // only once the content they describe has been read
+    if let Some(validators) = validators {
+        db_state.etag = validators.etag;
+        db_state.last_modified = validators.last_modified;
+    }
+    if db_state.hash.as_ref() == Some(&hash) {
+        tracing::debug!("The content of {} did not change", source);
+        return Ok(None);
+    }
+    let (game_db, parse_errors) = parse_db_with_errors(&content);
+    for parse_error in &parse_errors {
+        tracing::warn!(
+            "{}: line {} rejected by the parser: {}",
+            source,
+            parse_error.line,
+            parse_error.content
+        );
+    }
+    db_state.hash = Some(hash);
+    db_state.last_fetch = db_state.last_check;
+    db_state.parse_errors = parse_errors;
+    Ok(Some(game_db))
+}
+
+fn get_hash(content: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+// Returns the modification time of local databases, None for remote ones
+pub fn get_db_modified(source: &DatabaseSource) -> Option<SystemTime> {
+    let path = match source {
+        DatabaseSource::Url(_) => return None,
+        DatabaseSource::File(path) => path.to_owned(),
+        DatabaseSource::Git { repository, file } => repository.join(file),
+    };
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// The validators of a response, sent back with the next request
+// so the server answers 304 when the database did not change
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+// Returns the content with its validators, None when not modified
+async fn get_db_content_from_url(
+    db_url: &str,
+    db_state: &DbState,
+) -> anyhow::Result<Option<(String, Validators)>> {
+    let mut req = reqwest::Client::new().get(db_url);
+    if let Some(ref etag) = db_state.etag {
+        req = req.header(header::IF_NONE_MATCH, etag);
+    }
+    if let Some(ref last_modified) = db_state.last_modified {
+        req = req.header(header::IF_MODIFIED_SINCE, last_modified);
+    }
+    let resp = req
+        .send()
         .await
         .context("Failed to fetch playonbsd database")?;
-    let content = req
+    if resp.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    let resp = resp
+        .error_for_status()
+        .context("Failed to fetch playonbsd database")?;
+    let get_header = |name: header::HeaderName| -> Option<String> {
+        resp.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    };
+    let validators = Validators {
+        etag: get_header(header::ETAG),
+        last_modified: get_header(header::LAST_MODIFIED),
+    };
+    let content = resp
         .text()
         .await
         .context("Failed to read playonbsd database content")?;
-    let games = match Parser::default().load_from_string(&content) {
-        ParserResult::WithoutError(games) => games,
-        ParserResult::WithError(games, _) => games,
+    Ok(Some((content, validators)))
+}
+
+async fn get_db_content_from_file(path: &Path) -> anyhow::Result<String> {
+    tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read playonbsd database {}", path.display()))
+}
+
+async fn pull_git_repository(repository: &Path) -> anyhow::Result<()> {
+    let output = tokio::process::Command::new(GIT_PATH)
+        .arg("-C")
+        .arg(repository)
+        .args(["pull", "--ff-only", "--quiet"])
+        .output()
+        .await
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to pull {}: {}",
+            repository.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+pub fn parse_db(content: &str) -> GameDataBase {
+    parse_db_with_errors(content).0
+}
+
+// The parser only gives the line numbers of the errors, starting at 1
+pub fn parse_db_with_errors(content: &str) -> (GameDataBase, Vec<ParseError>) {
+    let (games, error_lines) = match Parser::default().load_from_string(content) {
+        ParserResult::WithoutError(games) => (games, vec![]),
+        ParserResult::WithError(games, error_lines) => (games, error_lines),
     };
-    let db = GameDataBase::new(games);
-    Ok(db)
+    let lines: Vec<&str> = content.lines().collect();
+    let parse_errors = error_lines
+        .into_iter()
+        .map(|line| ParseError {
+            line,
+            content: lines
+                .get(line.saturating_sub(1))
+                .map(|content| content.to_string())
+                .unwrap_or_default(),
+        })
+        .collect();
+    (GameDataBase::new(games), parse_errors)
 }
 
 fn add_query_string(field: &Option<String>, field_name: &str, query_string: &mut Vec<String>) {
@@ -54,19 +253,6 @@ impl GameFilterWrapper {
     pub fn new(filter: GameFilter) -> Self {
         GameFilterWrapper(filter)
     }
-
-    pub fn from_pattern(pattern: &str) -> Self {
-        let mut filter = GameFilter::default();
-        filter.set_name(pattern);
-        filter.set_engine(pattern);
-        filter.set_runtime(pattern);
-        filter.set_genre(pattern);
-        filter.set_tag(pattern);
-        filter.set_year(pattern);
-        filter.set_dev(pattern);
-        filter.set_publi(pattern);
-        Self(filter)
-    }
 }
 
 impl Deref for GameFilterWrapper {
@@ -82,14 +268,126 @@ impl DerefMut for GameFilterWrapper {
     }
 }
 
-pub fn get_subscriber() -> impl Subscriber {
+// Resolves when the server is asked to stop by SIGINT or SIGTERM
+pub async fn shutdown_signal() {
+    let mut sigterm = signal(SignalKind::terminate()).expect("Failed to handle SIGTERM");
+    let mut sigint = signal(SignalKind::interrupt()).expect("Failed to handle SIGINT");
+    tokio::select! {
+        _ = sigterm.recv() => tracing::info!("Received SIGTERM"),
+        _ = sigint.recv() => tracing::info!("Received SIGINT"),
+    }
+}
+
+// Sleep for the given number of seconds, returns
+// false if the shutdown was requested in the meantime
+pub async fn sleep_or_shutdown(period: u64, shutdown: &mut WatchReceiver<bool>) -> bool {
+    if *shutdown.borrow() {
+        return false;
+    }
+    tokio::select! {
+        _ = tokio::time::sleep(tokio::time::Duration::from_secs(period)) => true,
+        _ = shutdown.changed() => false,
+    }
+}
+
+// Used to change the log filter of a running server
+pub type LogHandle = reload::Handle<EnvFilter, Registry>;
+
+// The filter set in the configuration takes precedence over POBSD_SERVER_LOG
+pub fn get_env_filter(filter: Option<&str>) -> EnvFilter {
+    let builder = EnvFilter::builder().with_default_directive(LevelFilter::INFO.into());
+    match filter {
+        Some(filter) => builder.parse_lossy(filter),
+        None => builder.with_env_var("POBSD_SERVER_LOG").from_env_lossy(),
+    }
+}
+
+pub fn set_log_filter(log_handle: &LogHandle, filter: Option<&str>) {
+    if let Err(e) = log_handle.reload(get_env_filter(filter)) {
+        tracing::warn!("Failed to change the log filter: {e}");
+    }
+}
+
+pub fn get_subscriber() -> (impl Subscriber, LogHandle) {
     let target_filter = Targets::new().with_target("pobsd_server", Level::TRACE);
-    let env_filter = EnvFilter::builder()
-        .with_env_var("POBSD_SERVER_LOG")
-        .with_default_directive(LevelFilter::INFO.into())
-        .from_env_lossy();
-    tracing_subscriber::registry()
-        .with(fmt::layer())
-        .with(target_filter)
+    let (env_filter, log_handle) = reload::Layer::new(get_env_filter(None));
+    let subscriber = tracing_subscriber::registry()
         .with(env_filter)
+        .with(fmt::layer())
+        .with(target_filter);
+    (subscriber, log_handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{test_db_content, TEST_DB_PATH};
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn test_get_db_from_file() {
+        let source = DatabaseSource::File(PathBuf::from(TEST_DB_PATH));
+        let mut db_state = DbState::default();
+        let db = get_db(&source, &mut db_state).await.unwrap().unwrap();
+        assert_eq!(db.get_all_games().count, 9);
+        assert!(get_db_modified(&source).is_some());
+        assert!(db_state.hash.is_some());
+        assert_eq!(db_state.last_fetch, db_state.last_check);
+        // the same content is not parsed again
+        assert!(get_db(&source, &mut db_state).await.unwrap().is_none());
+        assert!(db_state.last_fetch < db_state.last_check);
+    }
+    #[test]
+    fn test_parse_errors_are_kept() {
+        let content = test_db_content();
+        let (game_db, parse_errors) = parse_db_with_errors(&content);
+        assert!(parse_errors.is_empty());
+        let count = game_db.get_all_games().count;
+        let content = content.replacen("Setup\n", "Setup\nNotAField\tvalue\n", 1);
+        let (_, parse_errors) = parse_db_with_errors(&content);
+        assert_eq!(
+            parse_errors,
+            vec![ParseError {
+                line: 5,
+                content: "NotAField\tvalue".into()
+            }]
+        );
+        // the rest of the entry is still parsed
+        assert_eq!(parse_db(&content).get_all_games().count, count);
+    }
+    #[tokio::test]
+    async fn test_sleep_is_interrupted_by_shutdown() {
+        let (shutdown_tx, mut shutdown) = tokio::sync::watch::channel(false);
+        let start = std::time::Instant::now();
+        let sleeper = tokio::spawn(async move { sleep_or_shutdown(60, &mut shutdown).await });
+        shutdown_tx.send(true).unwrap();
+        assert!(!sleeper.await.unwrap());
+        assert!(start.elapsed().as_secs() < 60);
+    }
+    #[tokio::test]
+    async fn test_get_db_from_missing_file() {
+        let source = DatabaseSource::File(PathBuf::from("tests/db/doesnotexist.db"));
+        let mut db_state = DbState::default();
+        assert!(get_db(&source, &mut db_state).await.is_err());
+        assert!(db_state.last_check.is_none());
+        assert!(get_db_modified(&source).is_none());
+    }
+    #[tokio::test]
+    async fn test_validators_are_kept_only_with_the_content() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await;
+            // the connection is closed before the end of the body
+            let response = "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 100\r\n\r\nGame";
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+        let source = DatabaseSource::Url(format!("http://{}/openbsd-games.db", address));
+        let mut db_state = DbState::default();
+        assert!(get_db(&source, &mut db_state).await.is_err());
+        assert!(db_state.etag.is_none());
+    }
 }